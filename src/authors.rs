@@ -0,0 +1,106 @@
+//! Everyone who writes for the blog. Profiles are kept in a YAML file next to the posts, keyed by the id that posts name their authors
+//! by in their front matter.
+
+use meta::absolute_url;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error;
+use std::fs;
+use std::path::Path;
+
+#[derive(Deserialize, Hash, Eq, PartialEq, Debug, Clone)]
+pub struct AuthorLink {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Deserialize, Hash, Eq, PartialEq, Debug, Clone)]
+pub struct Author {
+    /// Filled in from the key of the profile
+    #[serde(skip)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub bio: Option<String>,
+    /// URL of a picture of them
+    #[serde(default)]
+    pub avatar: Option<String>,
+    #[serde(default)]
+    pub links: Vec<AuthorLink>,
+}
+
+impl Author {
+    /// For authors without a profile, named directly in a post's front matter
+    pub fn unregistered(name: &str, id: String) -> Author {
+        Author {
+            id,
+            name: name.to_string(),
+            bio: None,
+            avatar: None,
+            links: Vec::new(),
+        }
+    }
+
+    /// Relative to the site root
+    pub fn url(&self) -> String {
+        format!("/blog/author/{}", self.id)
+    }
+
+    /// A schema.org Person, for structured data
+    pub fn to_person(&self, public_url: &str) -> Value {
+        let mut person = json!({
+            "@type": "Person",
+            "name": self.name,
+            "url": format!("{}{}", public_url, self.url()),
+        });
+        if let Some(ref bio) = self.bio {
+            person["description"] = json!(bio);
+        }
+        if let Some(ref avatar) = self.avatar {
+            person["image"] = json!(absolute_url(public_url, avatar));
+        }
+        if !self.links.is_empty() {
+            person["sameAs"] = json!(self
+                .links
+                .iter()
+                .map(|link| absolute_url(public_url, &link.url))
+                .collect::<Vec<String>>());
+        }
+        person
+    }
+}
+
+/// Profiles by id. A missing file just means nobody has a profile yet.
+pub fn load(path: &Path) -> Result<BTreeMap<String, Author>, Box<error::Error>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let mut authors: BTreeMap<String, Author> = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+    authors
+        .iter_mut()
+        .for_each(|(id, author)| author.id = id.clone());
+    Ok(authors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn people_are_structured() {
+        let author = Author {
+            id: "sameer".to_string(),
+            name: "Sameer Puri".to_string(),
+            bio: None,
+            avatar: Some("/files/favicon.png".to_string()),
+            links: vec![AuthorLink {
+                name: "GitHub".to_string(),
+                url: "https://github.com/sameer".to_string(),
+            }],
+        };
+        let person = author.to_person("https://purisa.me");
+        assert_eq!(person["url"], "https://purisa.me/blog/author/sameer");
+        assert_eq!(person["image"], "https://purisa.me/files/favicon.png");
+        assert_eq!(person["sameAs"][0], "https://github.com/sameer");
+    }
+}
@@ -1,7 +1,15 @@
 use askama::Template;
 use lazy_static::lazy_static;
+use std::env;
+
+/// Served from the folder of the same name in the working directory
+pub const ASSETS_URL_PREFIX: &str = "/files/";
 
 lazy_static! {
+    /// Where the site is publicly reachable, for anything that needs absolute URLs (i.e. feeds). Configured by PUBLIC_URL.
+    pub static ref PUBLIC_URL: String = env::var("PUBLIC_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| "https://purisa.me".to_string());
     static ref CSS_FILE_HASH: String = {
         let css_file_contents = include_str!("../files/style.css");
         let mut hasher = openssl::sha::Sha512::new();
@@ -36,6 +44,18 @@ lazy_static! {
     };
 }
 
+/// For HTML built by hand; safe in text and in quoted attribute values
+pub fn push_escaped(html: &mut String, text: &str) {
+    text.chars().for_each(|c| match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        _ => html.push(c),
+    });
+}
+
 #[derive(Clone, Hash, Eq, PartialEq, Debug)]
 pub struct NavItem {
     pub link: &'static str,
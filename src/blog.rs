@@ -1,86 +1,1268 @@
 use askama::Template;
+use authors::{self, Author};
 use base::*;
-use chrono::offset::Utc;
-use chrono::DateTime;
+use chrono::offset::{TimeZone, Utc};
+use chrono::{DateTime, Datelike, NaiveDate};
+use conditional::{self, Cached, Conditions};
 use err;
-use pulldown_cmark::{Options, Parser};
-use rocket::{http::Status, State};
-use std::collections::HashMap;
+use highlight;
+use history::{FileHistory, History};
+use math;
+use meta::{self, MetaImage, PageMeta};
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use post_images;
+use pulldown_cmark::{Event, Options, Parser, Tag as MarkdownTag};
+use resizer;
+use rocket::request::{self, FromRequest, Request};
+use rocket::{http::Status, response::Redirect, Outcome, State};
+use sanitizer;
+use search::{self, SearchIndex};
+use sitemap::SitemapUrl;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::env;
 use std::error;
 use std::fs;
 use std::io;
 use std::io::Read;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
 use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+use url::percent_encoding::{percent_decode, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
 
 const INDEX_MAX_SIZE: usize = 10;
 
-#[derive(Template, Clone)]
-#[template(path = "blog_index.html")]
+/// Shown when links to the blog index are shared
+const BLOG_DESCRIPTION: &str = "Sameer Puri's blog on code, art and hardware.";
+
+#[derive(Clone)]
 pub struct Blog {
     _parent: Arc<Base>,
+    /// Published posts, newest first
     index: Vec<Post>,
+    /// Drafts and posts scheduled for later, which can only be seen with a preview token
+    unpublished: Vec<Post>,
+    posts_by_slug: HashMap<String, Post>,
     posts_by_title: HashMap<String, Post>,
+    /// URLs of published posts by their file name, which is what they used to be addressed by
+    redirects: HashMap<String, String>,
+    /// Each tag's posts, newest first
+    posts_by_tag: BTreeMap<String, Vec<Post>>,
+    /// Documents are positions in the index
+    search_index: SearchIndex,
+    /// Profiles by id
+    authors: BTreeMap<String, Author>,
+    /// Each author's posts by their id, newest first
+    posts_by_author: BTreeMap<String, Vec<Post>>,
 }
 
 const FOLDER_PATH: &str = "./blog";
 
-#[get("/")]
-pub fn get_index(state: State<BlogState>) -> Blog {
-    state.inner().read().unwrap().clone()
+/// Kept with the posts so that it is watched along with them
+const AUTHORS_FILE_NAME: &str = "authors.yaml";
+
+/// The HTML posts may contain, see sanitizer.rs
+const SANITIZER_FILE_NAME: &str = "sanitizer.yaml";
+
+const SCHEDULER_INTERVAL_SECS: u64 = 60;
+
+const PREVIEW_SECRET_VAR: &str = "BLOG_PREVIEW_SECRET";
+
+lazy_static! {
+    /// Preview tokens are derived from this, so changing it revokes every preview link that has been handed out. Previews are disabled
+    /// when it isn't set.
+    static ref PREVIEW_SECRET: Option<String> = env::var(PREVIEW_SECRET_VAR).ok();
+}
+
+/// One page of at most INDEX_MAX_SIZE posts from the index
+#[derive(Template)]
+#[template(path = "blog_index.html")]
+pub struct Index {
+    _parent: Arc<Base>,
+    posts: Vec<Post>,
+    page: usize,
+    page_count: usize,
+    /// Newer posts
+    prev_page_url: Option<String>,
+    /// Older posts
+    next_page_url: Option<String>,
+}
+
+impl Index {
+    /// Pages are in the path rather than the query so that they can be exported as static files
+    fn page_url(page: usize) -> String {
+        if page == 1 {
+            "/blog".to_string()
+        } else {
+            format!("/blog/page/{}", page)
+        }
+    }
+
+    fn meta(&self) -> PageMeta {
+        PageMeta::website(
+            "Blog",
+            "Blog",
+            BLOG_DESCRIPTION,
+            &Index::page_url(self.page),
+        )
+    }
+
+    fn last_modified(&self) -> DateTime<Utc> {
+        self.posts
+            .iter()
+            .map(|post| post.updated)
+            .max()
+            .unwrap_or_else(|| Utc.timestamp(0, 0))
+    }
+}
+
+#[derive(Responder)]
+pub enum IndexResponse {
+    Index(Cached<Index>),
+    Redirect(Redirect),
 }
 
-#[get("/<post>")]
-pub fn get_post(state: State<BlogState>, post: String) -> Result<Post, Status> {
+/// Pages used to be chosen with `?page=`, so links to those are permanently redirected to the page's own URL
+#[get("/?<page>")]
+pub fn get_index(
+    state: State<BlogState>,
+    page: Option<usize>,
+    conditions: Conditions,
+) -> Result<IndexResponse, Status> {
+    match page {
+        Some(page) => Ok(IndexResponse::Redirect(Redirect::permanent(
+            Index::page_url(page),
+        ))),
+        None => get_index_page(state, 1, conditions).map(IndexResponse::Index),
+    }
+}
+
+#[get("/page/<page>")]
+pub fn get_index_page(
+    state: State<BlogState>,
+    page: usize,
+    conditions: Conditions,
+) -> Result<Cached<Index>, Status> {
     state
         .read()
         .unwrap()
-        .posts_by_title
-        .get(&post)
-        .map(|post| post.clone())
+        .index_page(page)
+        .map(|index| Cached {
+            last_modified: index.last_modified(),
+            cache_control: conditional::PUBLIC,
+            conditions,
+            inner: index,
+        })
+        .ok_or(Status::NotFound)
+}
+
+const FEED_MAX_SIZE: usize = 20;
+
+const SEARCH_MAX_RESULTS: usize = 20;
+
+const RELATED_MAX_SIZE: usize = 3;
+
+#[derive(Template)]
+#[template(path = "blog_rss.xml")]
+pub struct RssFeed {
+    public_url: &'static str,
+    updated: DateTime<Utc>,
+    posts: Vec<Post>,
+}
+
+#[get("/feed.xml")]
+pub fn get_rss_feed(state: State<BlogState>) -> RssFeed {
+    let (updated, posts) = state.read().unwrap().feed_posts();
+    RssFeed {
+        public_url: &PUBLIC_URL,
+        updated,
+        posts,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "blog_atom.xml")]
+pub struct AtomFeed {
+    public_url: &'static str,
+    updated: DateTime<Utc>,
+    posts: Vec<Post>,
+}
+
+#[get("/atom.xml")]
+pub fn get_atom_feed(state: State<BlogState>) -> AtomFeed {
+    let (updated, posts) = state.read().unwrap().feed_posts();
+    AtomFeed {
+        public_url: &PUBLIC_URL,
+        updated,
+        posts,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "blog_tag.html")]
+pub struct TagPage {
+    _parent: Arc<Base>,
+    tag: String,
+    posts: Vec<Post>,
+}
+
+impl TagPage {
+    fn meta(&self) -> PageMeta {
+        PageMeta::website(
+            "CollectionPage",
+            &format!("#{}", self.tag),
+            &format!("Posts tagged #{}", self.tag),
+            &Tag::from(self.tag.as_str()).url,
+        )
+    }
+}
+
+#[get("/tag/<tag>")]
+pub fn get_tag(state: State<BlogState>, tag: String) -> Result<TagPage, Status> {
+    let state = state.read().unwrap();
+    let tag = Tag::normalize(&tag);
+    state
+        .posts_by_tag
+        .get(&tag)
+        .map(|posts| TagPage {
+            _parent: state._parent.clone(),
+            tag: tag.clone(),
+            posts: posts.clone(),
+        })
+        .ok_or(Status::NotFound)
+}
+
+pub struct TagCount {
+    tag: Tag,
+    count: usize,
+    /// Font size in em, scaled between 1 and 2 by how many posts have the tag
+    size: f32,
+}
+
+#[derive(Template)]
+#[template(path = "blog_tags.html")]
+pub struct TagCloud {
+    _parent: Arc<Base>,
+    tags: Vec<TagCount>,
+}
+
+impl TagCloud {
+    fn meta(&self) -> PageMeta {
+        PageMeta::website(
+            "CollectionPage",
+            "Tags",
+            "Every tag on the blog",
+            "/blog/tags",
+        )
+    }
+}
+
+#[get("/tags")]
+pub fn get_tags(state: State<BlogState>) -> TagCloud {
+    let state = state.read().unwrap();
+    let max_count = state
+        .posts_by_tag
+        .values()
+        .map(|posts| posts.len())
+        .max()
+        .unwrap_or(1);
+    TagCloud {
+        _parent: state._parent.clone(),
+        tags: state
+            .posts_by_tag
+            .iter()
+            .map(|(tag, posts)| TagCount {
+                tag: Tag::from(tag.as_str()),
+                count: posts.len(),
+                size: 1.0 + posts.len() as f32 / max_count as f32,
+            })
+            .collect(),
+    }
+}
+
+pub struct MonthCount {
+    year: i32,
+    month: u32,
+    count: usize,
+}
+
+impl MonthCount {
+    fn url(&self) -> String {
+        format!("/blog/{}/{}", self.year, self.month)
+    }
+
+    fn name(&self) -> String {
+        month_name(self.month)
+    }
+}
+
+pub struct YearCount {
+    year: i32,
+    count: usize,
+    /// Newest first
+    months: Vec<MonthCount>,
+}
+
+#[derive(Template)]
+#[template(path = "blog_archive.html")]
+pub struct Archive {
+    _parent: Arc<Base>,
+    years: Vec<YearCount>,
+}
+
+impl Archive {
+    fn meta(&self) -> PageMeta {
+        PageMeta::website(
+            "CollectionPage",
+            "Archive",
+            "Every post on the blog by year and month",
+            "/blog/archive",
+        )
+    }
+}
+
+#[get("/archive")]
+pub fn get_archive(state: State<BlogState>) -> Archive {
+    let state = state.read().unwrap();
+    Archive {
+        _parent: state._parent.clone(),
+        years: state.archive(),
+    }
+}
+
+/// A year or a month of posts
+#[derive(Template)]
+#[template(path = "blog_period.html")]
+pub struct PeriodPage {
+    _parent: Arc<Base>,
+    /// i.e. "2019" or "May 2019"
+    period: String,
+    /// Relative to the site root
+    url: String,
+    /// Only set for years
+    months: Vec<MonthCount>,
+    posts: Vec<Post>,
+}
+
+impl PeriodPage {
+    fn meta(&self) -> PageMeta {
+        PageMeta::website(
+            "CollectionPage",
+            &self.period,
+            &format!("Posts from {}", self.period),
+            &self.url,
+        )
+    }
+}
+
+/// A year with published posts in it. Any other number is forwarded on to get_post, since slugs can be numbers too.
+pub struct ArchivedYear(YearCount);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ArchivedYear {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let year = match request.get_param::<i32>(0) {
+            Some(Ok(year)) => year,
+            _ => return Outcome::Forward(()),
+        };
+        let state = request.guard::<State<BlogState>>()?;
+        let year_count = state
+            .read()
+            .unwrap()
+            .archive()
+            .into_iter()
+            .find(|year_count| year_count.year == year);
+        match year_count {
+            Some(year_count) => Outcome::Success(ArchivedYear(year_count)),
+            None => Outcome::Forward(()),
+        }
+    }
+}
+
+// The archive routes are ranked after the ones under a static prefix like /tag, which they would otherwise collide with
+#[get("/<year>", rank = 1)]
+pub fn get_year(state: State<BlogState>, year: i32, archived: ArchivedYear) -> PeriodPage {
+    let state = state.read().unwrap();
+    PeriodPage {
+        _parent: state._parent.clone(),
+        period: year.to_string(),
+        url: format!("/blog/{}", year),
+        months: archived.0.months,
+        posts: state
+            .index
+            .iter()
+            .filter(|post| post.created.year() == year)
+            .cloned()
+            .collect(),
+    }
+}
+
+#[get("/<year>/<month>", rank = 1)]
+pub fn get_month(state: State<BlogState>, year: i32, month: u32) -> Result<PeriodPage, Status> {
+    let state = state.read().unwrap();
+    let posts: Vec<Post> = state
+        .index
+        .iter()
+        .filter(|post| post.created.year() == year && post.created.month() == month)
+        .cloned()
+        .collect();
+    if posts.is_empty() {
+        return Err(Status::NotFound);
+    }
+    Ok(PeriodPage {
+        _parent: state._parent.clone(),
+        period: format!("{} {}", month_name(month), year),
+        url: format!("/blog/{}/{}", year, month),
+        months: Vec::new(),
+        posts,
+    })
+}
+
+#[derive(Responder)]
+pub enum PostResponse {
+    Post(Cached<Post>),
+    Redirect(Redirect),
+}
+
+#[derive(Template)]
+#[template(path = "blog_author.html")]
+pub struct AuthorPage {
+    _parent: Arc<Base>,
+    author: Author,
+    posts: Vec<Post>,
+}
+
+impl AuthorPage {
+    fn meta(&self) -> PageMeta {
+        let description = self
+            .author
+            .bio
+            .clone()
+            .unwrap_or_else(|| format!("Posts by {}", self.author.name));
+        PageMeta {
+            kind: "profile",
+            image: self.author.avatar.as_ref().map(|avatar| MetaImage {
+                src: avatar.clone(),
+                alt: self.author.name.clone(),
+                width: None,
+                height: None,
+            }),
+            structured_data: meta::to_script_json(&json!({
+                "@context": "https://schema.org",
+                "@type": "ProfilePage",
+                "mainEntity": self.author.to_person(&PUBLIC_URL),
+            })),
+            ..PageMeta::website(
+                "ProfilePage",
+                &self.author.name,
+                &meta::text_to_description(&description),
+                &self.author.url(),
+            )
+        }
+    }
+}
+
+#[get("/author/<id>")]
+pub fn get_author(state: State<BlogState>, id: String) -> Result<AuthorPage, Status> {
+    let state = state.read().unwrap();
+    state
+        .posts_by_author
+        .get(&id)
+        .and_then(|posts| {
+            posts[0]
+                .authors
+                .iter()
+                .find(|author| author.id == id)
+                .map(|author| AuthorPage {
+                    _parent: state._parent.clone(),
+                    author: author.clone(),
+                    posts: posts.clone(),
+                })
+        })
+        .ok_or(Status::NotFound)
+}
+
+pub struct SearchResult {
+    post: Post,
+    /// Escaped HTML with the matching words marked
+    snippet: String,
+}
+
+#[derive(Template)]
+#[template(path = "blog_search.html")]
+pub struct SearchPage {
+    _parent: Arc<Base>,
+    query: String,
+    results: Vec<SearchResult>,
+}
+
+#[get("/search?<q>")]
+pub fn get_search(state: State<BlogState>, q: Option<String>) -> SearchPage {
+    let state = state.read().unwrap();
+    let query = q.unwrap_or_default();
+    SearchPage {
+        _parent: state._parent.clone(),
+        results: state
+            .search_index
+            .search(&query)
+            .into_iter()
+            .take(SEARCH_MAX_RESULTS)
+            .map(|(document, _)| {
+                let post = &state.index[document];
+                SearchResult {
+                    snippet: search::snippet(&post.text, &query),
+                    post: post.clone(),
+                }
+            })
+            .collect(),
+        query,
+    }
+}
+
+/// Posts used to be addressed by their file name, so links to those are permanently redirected to the slug. Unpublished posts are only
+/// served when `preview` matches their preview token.
+// Ranked below get_year, which forwards anything that isn't a year with posts in it to here
+#[get("/<slug>?<preview>", rank = 2)]
+pub fn get_post(
+    state: State<BlogState>,
+    slug: String,
+    preview: Option<String>,
+    conditions: Conditions,
+) -> Result<PostResponse, Status> {
+    let state = state.read().unwrap();
+    if let Some(post) = state.posts_by_slug.get(&slug) {
+        return Ok(PostResponse::Post(Cached {
+            last_modified: post.updated,
+            cache_control: conditional::PUBLIC,
+            conditions,
+            inner: post.clone(),
+        }));
+    }
+    if let Some(url) = state.redirects.get(&slug) {
+        return Ok(PostResponse::Redirect(Redirect::permanent(url.clone())));
+    }
+    preview
+        .and_then(|preview| {
+            state.unpublished.iter().find(|post| {
+                post.slug == slug && post.preview_token_matches(PREVIEW_SECRET.as_ref(), &preview)
+            })
+        })
+        .map(|post| {
+            let mut post = post.clone();
+            post.preview = true;
+            PostResponse::Post(Cached {
+                last_modified: post.updated,
+                cache_control: conditional::PRIVATE,
+                conditions,
+                inner: post,
+            })
+        })
         .ok_or(Status::NotFound)
 }
 
-type BlogState = RwLock<Blog>;
+pub type BlogState = Arc<RwLock<Blog>>;
 impl Blog {
-    fn initialize(&mut self) {
-        match env::current_dir().and_then(|cwd_path_buf| {
+    fn is_post(path: &PathBuf) -> bool {
+        path.extension()
+            .map_or(false, |extension| extension == "md")
+    }
+
+    /// Looks an author up by id, or by name for posts that predate the profiles
+    fn find_author<'a>(authors: &'a BTreeMap<String, Author>, id: &str) -> Option<&'a Author> {
+        authors
+            .get(id)
+            .or_else(|| authors.values().find(|author| author.name == id))
+    }
+
+    /// Ids for the authors posts name without a profile, slugified from their names. Names that slugify to nothing or to an id that is
+    /// already taken are numbered, in order of name so that the ids don't depend on the order the posts were loaded in.
+    fn unregistered_author_ids(&self) -> HashMap<String, String> {
+        let names: BTreeSet<&String> = self
+            .index
+            .iter()
+            .chain(self.unpublished.iter())
+            .flat_map(|post| post.author_ids.iter())
+            .filter(|id| Blog::find_author(&self.authors, id).is_none())
+            .collect();
+        let mut ids: HashSet<String> = self.authors.keys().cloned().collect();
+        names
+            .into_iter()
+            .map(|name| {
+                let id = slugify(name);
+                let id = number_if_taken(&mut ids, if id.is_empty() { "author" } else { &id });
+                (name.clone(), id)
+            })
+            .collect()
+    }
+
+    fn load_authors(&mut self) {
+        let path = PathBuf::from(FOLDER_PATH).join(AUTHORS_FILE_NAME);
+        if !path.exists() {
+            info!("No authors file, posts will only show their authors' names");
+            self.authors = BTreeMap::new();
+            return;
+        }
+        match authors::load(&path) {
+            Ok(authors) => {
+                info!("Found {} authors", authors.len());
+                self.authors = authors;
+            }
+            // The previous profiles stay up
+            Err(err) => warn!("Couldn't load authors: {}", err),
+        }
+    }
+
+    /// Needs to happen before any posts are rendered
+    fn load_sanitizer() {
+        match sanitizer::load(&PathBuf::from(FOLDER_PATH).join(SANITIZER_FILE_NAME)) {
+            Ok(()) => info!("Loaded sanitizer policy"),
+            // Either the previous policy stays up or, on startup, posts are limited to ammonia's defaults
+            Err(err) => warn!("Couldn't load sanitizer policy: {}", err),
+        }
+    }
+
+    /// Re-sorts the index and rebuilds the lookup tables derived from it. Callers hold the write lock, so readers never see them out of
+    /// sync.
+    fn reindex(&mut self) {
+        let unregistered_ids = self.unregistered_author_ids();
+        let authors = &self.authors;
+        self.index
+            .iter_mut()
+            .chain(self.unpublished.iter_mut())
+            .for_each(|post| {
+                post.authors = post
+                    .author_ids
+                    .iter()
+                    .map(|id| match Blog::find_author(authors, id) {
+                        Some(author) => author.clone(),
+                        // Anyone without a profile gets a bare one with just their name
+                        None => Author::unregistered(id, unregistered_ids[id].clone()),
+                    })
+                    .collect()
+            });
+        self.assign_slugs();
+        self.index.sort_by(|a, b| {
+            b.created
+                .cmp(&a.created)
+                .then_with(|| a.title.cmp(&b.title))
+        });
+        let documents: Vec<String> = self
+            .index
+            .iter()
+            .map(|post| format!("{}\n{}", post.title, post.text))
+            .collect();
+        self.search_index = SearchIndex::new(documents.iter().map(String::as_str));
+        self.link_posts();
+        self.posts_by_slug = self
+            .index
+            .iter()
+            .map(|post| (post.slug.clone(), post.clone()))
+            .collect();
+        self.posts_by_title = self
+            .index
+            .iter()
+            .map(|post| (post.title.clone(), post.clone()))
+            .collect();
+        self.redirects = self
+            .index
+            .iter()
+            .filter_map(|post| Post::path_to_title(&post.path).map(|title| (title, post.url())))
+            .collect();
+        let mut posts_by_tag: BTreeMap<String, Vec<Post>> = BTreeMap::new();
+        self.index.iter().for_each(|post| {
+            post.tags.iter().for_each(|tag| {
+                posts_by_tag
+                    .entry(tag.name.clone())
+                    .or_insert_with(Vec::new)
+                    .push(post.clone())
+            })
+        });
+        self.posts_by_tag = posts_by_tag;
+        let mut posts_by_author: BTreeMap<String, Vec<Post>> = BTreeMap::new();
+        self.index.iter().for_each(|post| {
+            post.authors.iter().for_each(|author| {
+                posts_by_author
+                    .entry(author.id.clone())
+                    .or_insert_with(Vec::new)
+                    .push(post.clone())
+            })
+        });
+        self.posts_by_author = posts_by_author;
+    }
+
+    /// Posts that would share a slug are numbered, oldest first so that a new post never takes an existing one's URL. Published posts
+    /// come before unpublished ones for the same reason. Slugs the other routes would answer first are numbered too; numbers always are,
+    /// so that a post's URL doesn't change once posts are archived under that year.
+    fn assign_slugs(&mut self) {
+        let oldest_first = |a: &&mut Post, b: &&mut Post| {
+            a.created.cmp(&b.created).then_with(|| a.path.cmp(&b.path))
+        };
+        let mut published: Vec<&mut Post> = self.index.iter_mut().collect();
+        published.sort_by(oldest_first);
+        let mut unpublished: Vec<&mut Post> = self.unpublished.iter_mut().collect();
+        unpublished.sort_by(oldest_first);
+        let mut slugs: HashSet<String> =
+            RESERVED_SLUGS.iter().map(|slug| slug.to_string()).collect();
+        published.into_iter().chain(unpublished).for_each(|post| {
+            if post.base_slug.parse::<i32>().is_ok() {
+                slugs.insert(post.base_slug.clone());
+            }
+            post.slug = number_if_taken(&mut slugs, &post.base_slug);
+            if post.slug != post.base_slug {
+                warn!(
+                    "{} can't have the same slug as an older post or another page, so it is at {} instead",
+                    post.title,
+                    post.url()
+                );
+            }
+        });
+    }
+
+    /// Points each post at its neighbors by date and at the posts most like it. Posts sharing more tags are more related, with the
+    /// similarity of their text (which is at most 1) breaking ties.
+    fn link_posts(&mut self) {
+        let links: Vec<PostLink> = self.index.iter().map(PostLink::from).collect();
+        let related: Vec<Vec<PostLink>> = (0..self.index.len())
+            .map(|i| {
+                let mut scores: HashMap<usize, f64> =
+                    self.search_index.similar(i).into_iter().collect();
+                self.index.iter().enumerate().for_each(|(j, other)| {
+                    let shared_tags = other
+                        .tags
+                        .iter()
+                        .filter(|tag| self.index[i].tags.contains(tag))
+                        .count();
+                    if i != j && shared_tags > 0 {
+                        *scores.entry(j).or_insert(0.0) += shared_tags as f64;
+                    }
+                });
+                let mut scores: Vec<(usize, f64)> = scores.into_iter().collect();
+                scores.sort_by(|a, b| {
+                    b.1.partial_cmp(&a.1)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                        .then_with(|| a.0.cmp(&b.0))
+                });
+                scores
+                    .into_iter()
+                    .take(RELATED_MAX_SIZE)
+                    .map(|(j, _)| links[j].clone())
+                    .collect()
+            })
+            .collect();
+        self.index
+            .iter_mut()
+            .zip(related)
+            .enumerate()
+            .for_each(|(i, (post, related))| {
+                post.newer = i.checked_sub(1).map(|newer| links[newer].clone());
+                post.older = links.get(i + 1).cloned();
+                post.related = related;
+            });
+    }
+
+    /// Every page of the blog that doesn't depend on a query from the reader, for exporting the site. Search results and previews are
+    /// left out.
+    pub fn export_urls(&self) -> Vec<String> {
+        let page_count = std::cmp::max(1, (self.index.len() + INDEX_MAX_SIZE - 1) / INDEX_MAX_SIZE);
+        let mut urls: Vec<String> = vec![
+            "/blog".to_string(),
+            "/blog/feed.xml".to_string(),
+            "/blog/atom.xml".to_string(),
+            "/blog/tags".to_string(),
+            "/blog/archive".to_string(),
+        ];
+        urls.extend((2..=page_count).map(Index::page_url));
+        urls.extend(
+            self.posts_by_tag
+                .keys()
+                .map(|tag| Tag::from(tag.as_str()).url),
+        );
+        urls.extend(
+            self.posts_by_author
+                .keys()
+                .map(|id| format!("/blog/author/{}", id)),
+        );
+        self.archive().iter().for_each(|year_count| {
+            urls.push(format!("/blog/{}", year_count.year));
+            urls.extend(year_count.months.iter().map(MonthCount::url));
+        });
+        self.index.iter().for_each(|post| {
+            urls.push(post.url());
+            urls.extend(post.image_variants.iter().cloned());
+        });
+        urls
+    }
+
+    /// Every public page on the blog apart from the navigational ones (later index pages, the archive by period, search)
+    pub fn sitemap_urls(&self) -> Vec<SitemapUrl> {
+        let mut urls = vec![
+            SitemapUrl::new("/blog", Blog::last_modified(&self.index)),
+            SitemapUrl::new("/blog/tags", Blog::last_modified(&self.index)),
+            SitemapUrl::new("/blog/archive", Blog::last_modified(&self.index)),
+        ];
+        urls.extend(self.posts_by_tag.iter().map(|(tag, posts)| {
+            SitemapUrl::new(&Tag::from(tag.as_str()).url, Blog::last_modified(posts))
+        }));
+        urls.extend(self.posts_by_author.iter().map(|(id, posts)| {
+            SitemapUrl::new(&format!("/blog/author/{}", id), Blog::last_modified(posts))
+        }));
+        urls.extend(
+            self.index
+                .iter()
+                .map(|post| SitemapUrl::new(&post.url(), Some(post.updated))),
+        );
+        urls
+    }
+
+    fn last_modified(posts: &[Post]) -> Option<DateTime<Utc>> {
+        posts.iter().map(|post| post.updated).max()
+    }
+
+    /// Post counts by year and month, newest first
+    fn archive(&self) -> Vec<YearCount> {
+        let mut years: Vec<YearCount> = Vec::new();
+        self.index.iter().for_each(|post| {
+            let (year, month) = (post.created.year(), post.created.month());
+            if years.last().map_or(true, |last| last.year != year) {
+                years.push(YearCount {
+                    year,
+                    count: 0,
+                    months: Vec::new(),
+                });
+            }
+            let year_count = years.last_mut().unwrap();
+            year_count.count += 1;
+            if year_count
+                .months
+                .last()
+                .map_or(true, |last| last.month != month)
+            {
+                year_count.months.push(MonthCount {
+                    year,
+                    month,
+                    count: 0,
+                });
+            }
+            year_count.months.last_mut().unwrap().count += 1;
+        });
+        years
+    }
+
+    /// Titles of published posts and their URLs, for resolving links between posts
+    fn post_urls(&self) -> HashMap<String, String> {
+        self.posts_by_title
+            .iter()
+            .map(|(title, post)| (title.clone(), post.url()))
+            .collect()
+    }
+
+    /// Titles of the published posts at `paths` and the URLs they will have, read from their front matter alone so that posts can link
+    /// to ones that haven't been rendered yet. Posts whose slugs end up numbered by assign_slugs are the only ones this gets wrong.
+    fn front_matter_urls(paths: &[PathBuf]) -> HashMap<String, String> {
+        let now = Utc::now();
+        paths
+            .iter()
+            .filter_map(|path| {
+                let markdown_text = Post::path_to_markdown(path).ok()?;
+                let (front_matter, _) = FrontMatter::split(&markdown_text).ok()?;
+                let publish_at = match front_matter.publish_at {
+                    Some(ref publish_at) => Some(FrontMatter::parse_date(publish_at).ok()?),
+                    None => None,
+                };
+                if front_matter.draft || publish_at.map_or(false, |publish_at| publish_at > now) {
+                    return None;
+                }
+                let title = front_matter
+                    .title
+                    .clone()
+                    .or_else(|| Post::path_to_title(path))?;
+                let slug = Post::to_base_slug(front_matter.slug.as_ref(), &title, path)?;
+                Some((title, slug_to_url(&slug)))
+            })
+            .collect()
+    }
+
+    /// Posts whose links to other posts would now resolve differently, since the posts they link to may have been added, renamed or
+    /// removed since they were rendered
+    fn stale_paths(&self) -> Vec<PathBuf> {
+        let post_urls = self.post_urls();
+        self.index
+            .iter()
+            .chain(self.unpublished.iter())
+            .filter(|post| {
+                post.post_links
+                    .iter()
+                    .any(|(post_title, url)| post_urls.get(post_title) != url.as_ref())
+            })
+            .map(|post| post.path.clone())
+            .collect()
+    }
+
+    /// Re-renders the posts with stale links
+    fn relink(blog_state: &BlogState) {
+        let stale = blog_state.read().unwrap().stale_paths();
+        if !stale.is_empty() {
+            Blog::rerender(blog_state, &stale);
+        }
+    }
+
+    /// Renders the posts at `paths` again, keeping the current version of any that can't be. The markdown is rendered before taking the
+    /// write lock so requests aren't blocked on it.
+    fn rerender(blog_state: &BlogState, paths: &[PathBuf]) {
+        let context = Blog::render_context(blog_state);
+        let posts = Blog::render_posts(&context, paths);
+        blog_state.write().unwrap().replace_posts(posts);
+    }
+
+    /// Reads the history without holding the lock
+    fn render_context(blog_state: &BlogState) -> RenderContext {
+        let (parent, post_urls) = {
+            let state = blog_state.read().unwrap();
+            (state._parent.clone(), state.post_urls())
+        };
+        RenderContext::new(parent, post_urls)
+    }
+
+    /// Skips any posts that can't be rendered
+    fn render_posts(context: &RenderContext, paths: &[PathBuf]) -> Vec<Post> {
+        paths
+            .iter()
+            .filter_map(|path| match Post::try_from((context, path)) {
+                Ok(post) => Some(post),
+                Err(err) => {
+                    warn!("Couldn't derive post by path: {}", err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Swaps in new versions of posts. Any that were removed in the meantime stay removed, and any that the watcher rendered from a newer
+    /// version of the file in the meantime stay as they are.
+    fn replace_posts(&mut self, posts: Vec<Post>) {
+        posts.into_iter().for_each(|post| {
+            let existing_modified = self
+                .index
+                .iter()
+                .chain(self.unpublished.iter())
+                .find(|existing| existing.path == post.path)
+                .map(|existing| existing.modified);
+            match existing_modified {
+                Some(existing_modified) if existing_modified > post.modified => {
+                    debug!("Keeping the newer version of {}", post.title)
+                }
+                Some(_) => {
+                    debug!("Rerendering {}", post.title);
+                    self.index.retain(|existing| existing.path != post.path);
+                    self.unpublished
+                        .retain(|existing| existing.path != post.path);
+                    self.insert_post(post);
+                }
+                None => {}
+            }
+        });
+        self.reindex();
+    }
+
+    /// Files the post as published or unpublished without reindexing
+    fn insert_post(&mut self, post: Post) {
+        if post.is_published(&Utc::now()) {
+            debug!("Adding {}", post.title);
+            self.index.push(post);
+        } else {
+            match post.preview_url() {
+                Some(preview_url) => {
+                    debug!("Holding back {}, preview at {}", post.title, preview_url)
+                }
+                None => debug!(
+                    "Holding back {}, set {} to preview it",
+                    post.title, PREVIEW_SECRET_VAR
+                ),
+            }
+            self.unpublished.push(post);
+        }
+    }
+
+    /// Replaces any post previously loaded from the same file
+    fn upsert_post(&mut self, post: Post) {
+        self.index.retain(|existing| existing.path != post.path);
+        self.unpublished
+            .retain(|existing| existing.path != post.path);
+        self.insert_post(post);
+        self.reindex();
+    }
+
+    fn remove_post(&mut self, path_to_remove: &PathBuf) -> Option<Post> {
+        let removed = match self
+            .index
+            .iter()
+            .position(|post| &post.path == path_to_remove)
+        {
+            Some(pos_to_remove) => Some(self.index.swap_remove(pos_to_remove)),
+            None => self
+                .unpublished
+                .iter()
+                .position(|post| &post.path == path_to_remove)
+                .map(|pos_to_remove| self.unpublished.swap_remove(pos_to_remove)),
+        };
+        self.reindex();
+        removed
+    }
+
+    /// Moves scheduled posts whose time has come into the index, returning how many were published. Posts linking to them still need to be
+    /// relinked.
+    fn publish_due(&mut self, now: &DateTime<Utc>) -> usize {
+        let (due, unpublished): (Vec<Post>, Vec<Post>) = self
+            .unpublished
+            .drain(..)
+            .partition(|post| post.is_published(now));
+        self.unpublished = unpublished;
+        let published = due.len();
+        if published > 0 {
+            due.into_iter().for_each(|post| {
+                info!("Publishing scheduled post {}", post.title);
+                self.index.push(post);
+            });
+            self.reindex();
+        }
+        published
+    }
+
+    /// Pages are numbered from 1. There is always at least one page so an empty blog can still say so.
+    fn index_page(&self, page: usize) -> Option<Index> {
+        let page_count = std::cmp::max(1, (self.index.len() + INDEX_MAX_SIZE - 1) / INDEX_MAX_SIZE);
+        if page == 0 || page > page_count {
+            return None;
+        }
+        Some(Index {
+            _parent: self._parent.clone(),
+            posts: self
+                .index
+                .iter()
+                .skip((page - 1) * INDEX_MAX_SIZE)
+                .take(INDEX_MAX_SIZE)
+                .cloned()
+                .collect(),
+            page,
+            page_count,
+            prev_page_url: if page > 1 {
+                Some(Index::page_url(page - 1))
+            } else {
+                None
+            },
+            next_page_url: if page < page_count {
+                Some(Index::page_url(page + 1))
+            } else {
+                None
+            },
+        })
+    }
+
+    /// The newest FEED_MAX_SIZE posts, along with when any of them last changed
+    fn feed_posts(&self) -> (DateTime<Utc>, Vec<Post>) {
+        let posts: Vec<Post> = self.index.iter().take(FEED_MAX_SIZE).cloned().collect();
+        let updated = posts
+            .iter()
+            .map(|post| post.updated)
+            .max()
+            .unwrap_or_else(Utc::now);
+        (updated, posts)
+    }
+
+    /// Loads every post in the folder. Their titles and URLs are read from the front matter first, so that each post only needs to be
+    /// rendered once no matter which posts it links to.
+    fn initialize(blog_state: &BlogState) {
+        let parent = {
+            let mut state = blog_state.write().unwrap();
+            state.load_authors();
+            state._parent.clone()
+        };
+        Blog::load_sanitizer();
+        let dir_iter = match env::current_dir().and_then(|cwd_path_buf| {
             let gallery_prefix = cwd_path_buf.join(PathBuf::from(FOLDER_PATH));
             fs::read_dir(gallery_prefix)
         }) {
-            Ok(dir_iter) => {
-                let length_before = self.posts_by_title.len();
-                let path_iter = dir_iter
-                    .map(|dir_entry_result| dir_entry_result.map(|dir_entry| dir_entry.path()));
-                path_iter.for_each(|path_result| {
-                    match path_result {
-                        Ok(path) => match Post::try_from((self._parent.clone(), &path)) {
-                            Ok(post) => {
-                                debug!("Adding {}", post.title);
-                                self.posts_by_title.insert(post.title.clone(), post.clone());
-                                self.index.push(post.clone());
-                            }
-                            Err(err) => warn!("Couldn't derive new post by path: {}", err),
-                        },
-                        Err(err) => {
-                            warn!("Error while reading file from directory: {}", err);
-                        }
-                    };
-                });
-                let length_after = self.posts_by_title.len();
-                info!("Found {} posts", length_after - length_before);
-            }
+            Ok(dir_iter) => dir_iter,
             Err(err) => {
                 error!("Error while reading files from directory: {}", err);
+                return;
             }
         };
+        let paths: Vec<PathBuf> = dir_iter
+            .filter_map(|dir_entry_result| match dir_entry_result {
+                Ok(dir_entry) => Some(dir_entry.path()),
+                Err(err) => {
+                    warn!("Error while reading file from directory: {}", err);
+                    None
+                }
+            })
+            .filter(|path| {
+                if !Blog::is_post(path) {
+                    debug!("Ignoring non-markdown file {:?}", path);
+                }
+                Blog::is_post(path)
+            })
+            .collect();
+        let context = RenderContext::new(parent, Blog::front_matter_urls(&paths));
+        let posts = Blog::render_posts(&context, &paths);
+        {
+            let mut state = blog_state.write().unwrap();
+            posts.into_iter().for_each(|post| state.insert_post(post));
+            state.reindex();
+            info!("Found {} posts", state.index.len());
+        }
+        // Only rerenders anything if slugs had to be numbered
+        Blog::relink(blog_state);
+        let state = blog_state.read().unwrap();
+        let broken_links: usize = state
+            .index
+            .iter()
+            .chain(state.unpublished.iter())
+            .map(Post::warn_broken_links)
+            .sum();
+        if broken_links > 0 {
+            warn!("Found {} broken links in posts", broken_links);
+        }
     }
 
     pub fn new(parent: Arc<Base>) -> BlogState {
-        let mut blog = Blog::from(parent);
-        blog.initialize();
-        RwLock::new(blog)
+        let blog = Blog::load(parent);
+        Blog::spawn_updater(blog.clone());
+        Blog::spawn_scheduler(blog.clone());
+        blog
+    }
+
+    /// Loads every post without watching for changes or publishing scheduled posts, for when the blog won't be around for long (i.e.
+    /// exporting)
+    pub fn load(parent: Arc<Base>) -> BlogState {
+        let blog = Arc::new(RwLock::new(Blog::from(parent)));
+        Blog::initialize(&blog);
+        blog
+    }
+
+    /// Re-renders the post at `path`, dropping the post previously at `original_path` if it was renamed. If the new version can't be
+    /// parsed (i.e. an editor saved it half-written), the old version stays up.
+    fn handle_change(blog_state: &BlogState, original_path: Option<&PathBuf>, path: &PathBuf) {
+        let post = if Blog::is_post(path) {
+            let context = Blog::render_context(blog_state);
+            match Post::try_from((&context, path)) {
+                Ok(post) => {
+                    post.warn_broken_links();
+                    Some(post)
+                }
+                Err(err) => {
+                    warn!("Couldn't derive changed post by path: {}", err);
+                    return;
+                }
+            }
+        } else {
+            None
+        };
+        {
+            let mut state = blog_state.write().unwrap();
+            if let Some(original_path) = original_path {
+                if let Some(original_post) = state.remove_post(original_path) {
+                    debug!("Handling move from {:?} to {:?}", original_post.title, path);
+                }
+            }
+            if let Some(post) = post {
+                debug!("Handling changed {}", post.title);
+                state.upsert_post(post);
+            }
+        }
+        Blog::relink(blog_state);
+    }
+
+    /// Scheduled posts are published by polling rather than by sleeping until they are due, since the watcher may reschedule them at any
+    /// time.
+    fn spawn_scheduler(blog_state: BlogState) {
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(SCHEDULER_INTERVAL_SECS));
+            let now = Utc::now();
+            let any_due = blog_state
+                .read()
+                .unwrap()
+                .unpublished
+                .iter()
+                .any(|post| post.is_published(&now));
+            if any_due {
+                let published = blog_state.write().unwrap().publish_due(&now);
+                if published > 0 {
+                    Blog::relink(&blog_state);
+                }
+            }
+        });
+    }
+
+    fn spawn_updater(blog_state: BlogState) {
+        thread::spawn(move || {
+            let (tx, notify_event_receiver) = channel();
+            let mut watcher = watcher(tx, Duration::from_secs(2)).unwrap();
+            match watcher.watch(FOLDER_PATH, RecursiveMode::NonRecursive) {
+                Ok(()) => {}
+                Err(err) => {
+                    error!("Could not watch blog directory: {}", err);
+                    return;
+                }
+            }
+            loop {
+                match notify_event_receiver.recv() {
+                    Ok(event) => match event {
+                        // Renaming it away or into place is the same as removing or creating it
+                        DebouncedEvent::Create(ref path)
+                        | DebouncedEvent::Write(ref path)
+                        | DebouncedEvent::Remove(ref path)
+                        | DebouncedEvent::Rename(ref path, _)
+                        | DebouncedEvent::Rename(_, ref path)
+                            if path
+                                .file_name()
+                                .map_or(false, |name| name == AUTHORS_FILE_NAME) =>
+                        {
+                            let mut state = blog_state.write().unwrap();
+                            state.load_authors();
+                            state.reindex();
+                        }
+                        DebouncedEvent::Create(ref path) | DebouncedEvent::Write(ref path)
+                            if path
+                                .file_name()
+                                .map_or(false, |name| name == SANITIZER_FILE_NAME) =>
+                        {
+                            Blog::load_sanitizer();
+                            let paths: Vec<PathBuf> = {
+                                let state = blog_state.read().unwrap();
+                                state
+                                    .index
+                                    .iter()
+                                    .chain(state.unpublished.iter())
+                                    .map(|post| post.path.clone())
+                                    .collect()
+                            };
+                            Blog::rerender(&blog_state, &paths);
+                        }
+                        DebouncedEvent::Create(path) | DebouncedEvent::Write(path) => {
+                            Blog::handle_change(&blog_state, None, &path)
+                        }
+                        DebouncedEvent::Rename(original_path, renamed_path) => {
+                            Blog::handle_change(&blog_state, Some(&original_path), &renamed_path)
+                        }
+                        DebouncedEvent::Remove(removed_path) => {
+                            let removed = blog_state.write().unwrap().remove_post(&removed_path);
+                            if let Some(post) = removed {
+                                debug!("Handling removed {}", post.title);
+                                Blog::relink(&blog_state);
+                            }
+                        }
+                        _ => {}
+                    },
+                    Err(err) => {
+                        error!("Blog watcher disconnected: {}", err);
+                        return;
+                    }
+                }
+            }
+        });
     }
 }
 
@@ -89,40 +1271,427 @@ impl From<Arc<Base>> for Blog {
         Blog {
             _parent: parent,
             index: Vec::new(),
+            unpublished: Vec::new(),
+            posts_by_slug: HashMap::new(),
             posts_by_title: HashMap::new(),
+            redirects: HashMap::new(),
+            posts_by_tag: BTreeMap::new(),
+            search_index: SearchIndex::default(),
+            authors: BTreeMap::new(),
+            posts_by_author: BTreeMap::new(),
+        }
+    }
+}
+
+/// i.e. "May" for 5
+fn month_name(month: u32) -> String {
+    NaiveDate::from_ymd_opt(2000, month, 1)
+        .map(|date| date.format("%B").to_string())
+        .unwrap_or_else(|| month.to_string())
+}
+
+/// Lowercases and keeps only ASCII letters and digits, joining the runs between them with dashes.
+/// "0 to Video: HDMI output on an FPGA" --> "0-to-video-hdmi-output-on-an-fpga"
+fn slugify(text: &str) -> String {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+/// Slugifies `text`, numbering it if the slug was already taken, and marks it as taken
+fn unique_id(ids: &mut HashSet<String>, text: &str) -> String {
+    let id = slugify(text);
+    if id.is_empty() {
+        number_if_taken(ids, "section")
+    } else {
+        number_if_taken(ids, &id)
+    }
+}
+
+/// Numbers `id` if it was already taken, and marks it as taken
+fn number_if_taken(ids: &mut HashSet<String>, id: &str) -> String {
+    let unique_id = Some(id.to_string())
+        .into_iter()
+        .chain((1..).map(|n| format!("{}-{}", id, n)))
+        .find(|candidate| !ids.contains(candidate))
+        .unwrap();
+    ids.insert(unique_id.clone());
+    unique_id
+}
+
+/// Answered by get_tags, get_archive and get_search before get_post sees them
+const RESERVED_SLUGS: [&str; 3] = ["tags", "archive", "search"];
+
+/// Relative to the site root
+fn slug_to_url(slug: &str) -> String {
+    format!("/blog/{}", slug)
+}
+
+/// For posts that don't say who wrote them
+const DEFAULT_AUTHOR_ID: &str = "sameer";
+
+/// Metadata declared in a YAML block delimited by `---` lines at the very top of a post. Every field is optional; anything left out is
+/// derived from the file itself.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct FrontMatter {
+    title: Option<String>,
+    slug: Option<String>,
+    /// Id of the author's profile. Posts with more than one author list them in `authors` instead.
+    author: Option<String>,
+    authors: Vec<String>,
+    created: Option<String>,
+    updated: Option<String>,
+    tags: Vec<String>,
+    summary: Option<String>,
+    draft: bool,
+    publish_at: Option<String>,
+    /// Whether to show a table of contents. Left out, one is shown for posts with at least TOC_MIN_HEADINGS headings.
+    toc: Option<bool>,
+}
+
+const FRONT_MATTER_DELIMITER: &str = "---";
+
+impl FrontMatter {
+    /// Splits the front matter from the markdown that follows it. Files without front matter are returned untouched with default metadata.
+    fn split(markdown: &str) -> Result<(FrontMatter, &str), Box<error::Error>> {
+        let mut lines = markdown.splitn(2, '\n');
+        if lines.next().map(|line| line.trim_end()) != Some(FRONT_MATTER_DELIMITER) {
+            return Ok((FrontMatter::default(), markdown));
+        }
+        let rest = lines.next().unwrap_or("");
+        let mut offset = 0;
+        for line in rest.split('\n') {
+            if line.trim_end() == FRONT_MATTER_DELIMITER {
+                let yaml = &rest[..offset];
+                let markdown = rest.get(offset + line.len() + 1..).unwrap_or("");
+                return if yaml.trim().is_empty() {
+                    Ok((FrontMatter::default(), markdown))
+                } else {
+                    serde_yaml::from_str(yaml)
+                        .map(|front_matter| (front_matter, markdown))
+                        .map_err(|err| Box::new(err) as Box<error::Error>)
+                };
+            }
+            offset += line.len() + 1;
+        }
+        Err(From::from("Front matter was never closed"))
+    }
+
+    /// Accepts either a full RFC 3339 timestamp or a plain `YYYY-MM-DD` date, which is taken to be midnight UTC.
+    fn parse_date(date: &str) -> Result<DateTime<Utc>, Box<error::Error>> {
+        DateTime::parse_from_rfc3339(date)
+            .map(|datetime| datetime.with_timezone(&Utc))
+            .or_else(|_| {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map(|date| DateTime::from_utc(date.and_hms(0, 0, 0), Utc))
+            })
+            .map_err(|err| From::from(format!("Could not parse date {:?}: {}", date, err)))
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+pub struct Tag {
+    name: String,
+    /// Relative to the site root
+    url: String,
+}
+
+impl Tag {
+    /// Tags are case-insensitive, so "FPGA" and "fpga" share a page
+    fn normalize(tag: &str) -> String {
+        tag.trim().to_lowercase()
+    }
+}
+
+impl<'a> From<&'a str> for Tag {
+    fn from(tag: &'a str) -> Tag {
+        let name = Tag::normalize(tag);
+        Tag {
+            url: format!(
+                "/blog/tag/{}",
+                utf8_percent_encode(&name, PATH_SEGMENT_ENCODE_SET)
+            ),
+            name,
         }
     }
 }
 
 #[derive(Template, Hash, Eq, PartialEq, Debug, Clone)]
-#[template(path = "blog_page.html", escape = "none")]
+#[template(path = "blog_page.html")]
 pub struct Post {
     _parent: Arc<Base>,
+    path: PathBuf,
     title: String,
-    last_modified: String,
-    author: String,
+    /// Unique among all posts, see Blog::assign_slugs
+    slug: String,
+    /// From the front matter or the title, before any number was added to tell it apart from an older post's
+    base_slug: String,
+    created: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    /// When the file was last modified as of rendering, so that a slower render of an older version can't replace this one
+    modified: DateTime<Utc>,
+    author_ids: Vec<String>,
+    /// Looked up from `author_ids` whenever the blog is reindexed, since profiles can change without the post changing
+    authors: Vec<Author>,
+    tags: Vec<Tag>,
+    /// Sanitized HTML, from the front matter or else the start of the post
+    summary: Option<String>,
+    /// Plain text, from the summary in the front matter or else the start of the post
+    description: String,
+    /// The first image in the post, shown when links to it are shared
+    image: Option<MetaImage>,
+    draft: bool,
+    /// Hidden until then
+    publish_at: Option<DateTime<Utc>>,
+    /// Set when an unpublished post is served with its preview token
+    preview: bool,
     checksum: String,
+    /// Rendered table of contents, for long posts
+    toc: Option<String>,
+    /// Sanitized HTML
+    body: String,
+    /// Plain text of the body, for search
+    text: String,
+    word_count: usize,
+    /// Rounded up, so never zero
+    reading_minutes: usize,
+    /// Neighbors by date, only set for published posts
+    newer: Option<PostLink>,
+    older: Option<PostLink>,
+    related: Vec<PostLink>,
+    /// Titles of the posts linked to with `post:`, along with the URLs they resolved to when the post was rendered
+    post_links: Vec<(String, Option<String>)>,
+    /// Links and images under /files that point at nothing
+    missing_assets: Vec<String>,
+    /// Resizer URLs of the responsive images in the body
+    image_variants: Vec<String>,
+}
+
+/// Enough of a post to link to it from another
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+struct PostLink {
+    title: String,
+    url: String,
+}
+
+impl<'a> From<&'a Post> for PostLink {
+    fn from(post: &'a Post) -> PostLink {
+        PostLink {
+            title: post.title.clone(),
+            url: post.url(),
+        }
+    }
+}
+
+/// What rendering a post needs to know about the rest of the blog
+struct RenderContext {
+    parent: Arc<Base>,
+    /// Titles of published posts and their URLs, for resolving `post:` links
+    post_urls: HashMap<String, String>,
+    /// None if the blog isn't in a repository
+    history: Option<History>,
+}
+
+impl RenderContext {
+    /// Reads the history of every post at once, since walking it is by far the slowest part of rendering a post
+    fn new(parent: Arc<Base>, post_urls: HashMap<String, String>) -> RenderContext {
+        let history = match History::load(Path::new(FOLDER_PATH)) {
+            Ok(history) => Some(history),
+            Err(err) => {
+                debug!("No git history for the blog: {}", err);
+                None
+            }
+        };
+        RenderContext {
+            parent,
+            post_urls,
+            history,
+        }
+    }
+}
+
+/// What rendering a post's markdown produces
+struct RenderedMarkdown {
+    /// Sanitized HTML
     body: String,
+    headings: Vec<Heading>,
+    /// Plain text of the prose, without code blocks or math
+    text: String,
+    /// Sanitized HTML of the start of the post
+    excerpt: Option<String>,
+    post_links: Vec<(String, Option<String>)>,
+    missing_assets: Vec<String>,
+    image_variants: Vec<String>,
+    image: Option<MetaImage>,
+}
+
+/// Posts with fewer headings than this don't get a table of contents unless they ask for one
+const TOC_MIN_HEADINGS: usize = 5;
+
+/// Ends the excerpt of a post when it shouldn't just be the first paragraph
+const MORE_MARKER: &str = "<!-- more -->";
+
+/// Links to other posts are written as `[text](<post:Title of the Post>)`, so they keep working when the post's slug changes
+const POST_LINK_SCHEME: &str = "post:";
+
+/// A typical adult's reading speed for technical prose
+const WORDS_PER_MINUTE: usize = 200;
+
+struct Heading {
+    level: i32,
+    /// Anchor of the heading in the body
+    id: String,
+    /// Text of the heading, escaped, with any math rendered
+    html: String,
 }
 
 impl Post {
+    /// Relative to the site root
+    fn url(&self) -> String {
+        slug_to_url(&self.slug)
+    }
+
+    /// From the front matter or else the title, falling back to the file name for titles without any ASCII letters or digits. Posts
+    /// that would share it are told apart later on, see Blog::assign_slugs.
+    fn to_base_slug(slug: Option<&String>, title: &str, path: &PathBuf) -> Option<String> {
+        Some(slugify(slug.map_or(title, String::as_str)))
+            .into_iter()
+            .chain(Post::path_to_title(path).map(|stem| slugify(&stem)))
+            .find(|slug| !slug.is_empty())
+    }
+
+    fn is_published(&self, now: &DateTime<Utc>) -> bool {
+        !self.draft
+            && self
+                .publish_at
+                .map_or(true, |publish_at| publish_at <= *now)
+    }
+
+    /// Each post gets its own token so that sharing a preview of one post doesn't reveal any others. `secret` is PREVIEW_SECRET outside
+    /// of tests.
+    fn preview_token(&self, secret: Option<&String>) -> Option<String> {
+        secret.map(|secret| {
+            let hash = openssl::sha::sha256(format!("{}:{}", secret, self.slug).as_bytes());
+            base64::encode_config(&hash, base64::URL_SAFE_NO_PAD)
+        })
+    }
+
+    fn preview_token_matches(&self, secret: Option<&String>, preview: &str) -> bool {
+        self.preview_token(secret).map_or(false, |token| {
+            token.len() == preview.len()
+                && openssl::memcmp::eq(token.as_bytes(), preview.as_bytes())
+        })
+    }
+
+    fn meta(&self) -> PageMeta {
+        let url = format!("{}{}", *PUBLIC_URL, self.url());
+        let mut structured_data = json!({
+            "@context": "https://schema.org",
+            "@type": "BlogPosting",
+            "headline": self.title,
+            "description": self.description,
+            "url": url,
+            "mainEntityOfPage": url,
+            "datePublished": self.created.to_rfc3339(),
+            "dateModified": self.updated.to_rfc3339(),
+            "author": self
+                .authors
+                .iter()
+                .map(|author| author.to_person(&PUBLIC_URL))
+                .collect::<Vec<_>>(),
+            "keywords": self
+                .tags
+                .iter()
+                .map(|tag| tag.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", "),
+            "wordCount": self.word_count,
+        });
+        if let Some(ref image) = self.image {
+            structured_data["image"] = image.to_image_object();
+        }
+        PageMeta {
+            kind: "article",
+            image: self.image.clone(),
+            published: Some(self.created),
+            modified: Some(self.updated),
+            authors: self.authors.clone(),
+            tags: self.tags.iter().map(|tag| tag.name.clone()).collect(),
+            structured_data: meta::to_script_json(&structured_data),
+            ..PageMeta::website("BlogPosting", &self.title, &self.description, &self.url())
+        }
+    }
+
+    fn preview_url(&self) -> Option<String> {
+        self.preview_token(PREVIEW_SECRET.as_ref())
+            .map(|token| format!("{}?preview={}", self.url(), token))
+    }
+
+    /// Logs a warning for each link in the post that leads nowhere, returning how many there were
+    fn warn_broken_links(&self) -> usize {
+        let missing_posts: Vec<&String> = self
+            .post_links
+            .iter()
+            .filter(|(_, url)| url.is_none())
+            .map(|(post_title, _)| post_title)
+            .collect();
+        missing_posts
+            .iter()
+            .for_each(|post_title| warn!("{} links to a missing post: {}", self.title, post_title));
+        self.missing_assets
+            .iter()
+            .for_each(|asset| warn!("{} links to a missing asset: {}", self.title, asset));
+        missing_posts.len() + self.missing_assets.len()
+    }
+
     fn path_to_title(path: &PathBuf) -> Option<String> {
         path.file_stem()
             .and_then(|stem_os_str| stem_os_str.to_str())
             .and_then(|stem_str| Some(stem_str.to_string()))
     }
 
-    fn path_to_last_modified(path: &PathBuf) -> Result<String, io::Error> {
+    /// When the post was first committed, if it was, and when it last changed. Changes are taken from git where possible, since
+    /// modification times are reset by every checkout.
+    fn path_to_history(
+        path: &PathBuf,
+        history: Option<&History>,
+    ) -> Result<(Option<DateTime<Utc>>, DateTime<Utc>), io::Error> {
+        match history.and_then(|history| history.file_history(path)) {
+            Some(FileHistory {
+                created,
+                updated: Some(updated),
+            }) => Ok((Some(created), updated)),
+            // Edited since the last commit
+            Some(FileHistory {
+                created,
+                updated: None,
+            }) => Post::path_to_last_modified(path)
+                .map(|last_modified| (Some(created), last_modified)),
+            None => {
+                debug!("No git history for {:?}", path);
+                Post::path_to_last_modified(path).map(|last_modified| (None, last_modified))
+            }
+        }
+    }
+
+    /// Whether the post changed on a later day than it was created
+    fn was_updated(&self) -> bool {
+        self.updated.date() != self.created.date()
+    }
+
+    fn path_to_last_modified(path: &PathBuf) -> Result<DateTime<Utc>, io::Error> {
         fs::metadata(&path)
             .and_then(|metadata| metadata.modified())
-            .map(|systime| {
-                let datetime: DateTime<Utc> = systime.into();
-                datetime.to_rfc2822()
-            })
+            .map(|systime| systime.into())
     }
 
-    fn path_to_author(path: &PathBuf) -> Option<String> {
-        Some(String::new())
+    fn path_to_markdown(path: &PathBuf) -> Result<String, io::Error> {
+        let mut markdown_buf = String::new();
+        fs::File::open(&path)
+            .and_then(|mut file| file.read_to_string(&mut markdown_buf).map(|_| markdown_buf))
     }
 
     fn body_to_checksum(body: &String) -> String {
@@ -130,46 +1699,804 @@ impl Post {
         format!("sha512-{}", base64::encode(hash))
     }
 
-    fn path_to_body(path: &PathBuf) -> Result<String, io::Error> {
-        let mut markdown_buf = String::new();
-        fs::File::open(&path)
-            .and_then(|mut file| file.read_to_string(&mut markdown_buf).map(|_| markdown_buf))
-            .map(|markdown_text| {
-                let mut opts = Options::empty();
-                opts.insert(Options::ENABLE_FOOTNOTES);
-                let parser = Parser::new_ext(&markdown_text, opts);
-                let mut unsafe_html_text = String::new();
-                pulldown_cmark::html::push_html(&mut unsafe_html_text, parser);
-                ammonia::Builder::default()
-                    .add_tags(&["video"])
-                    .add_tag_attributes("video", &["controls", "src"])
-                    .add_tag_attributes("div", &["id"])
-                    .add_tag_attribute_values("div", "class", &["footnote-definition"])
-                    .add_tag_attribute_values("sup", "class", &["footnote-definition-label"])
-                    .clean(&*unsafe_html_text)
-                    .to_string()
-            })
+    /// Fenced code blocks are collected and replaced with their highlighted HTML
+    fn highlight_code_blocks<'a>(parser: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+        let mut events = Vec::new();
+        let mut code_block: Option<(String, String)> = None;
+        for event in parser {
+            code_block = match (code_block, event) {
+                (None, Event::Start(MarkdownTag::CodeBlock(info))) => {
+                    Some((info.to_string(), String::new()))
+                }
+                (Some((info, mut code)), Event::Text(text)) => {
+                    code.push_str(&text);
+                    Some((info, code))
+                }
+                (Some((info, code)), Event::End(MarkdownTag::CodeBlock(_))) => {
+                    events.push(Event::Html(highlight::code_block(&info, &code).into()));
+                    None
+                }
+                (code_block, event) => {
+                    events.push(event);
+                    code_block
+                }
+            };
+        }
+        events
     }
 
-    fn try_from<'a>((base, path): (Arc<Base>, &'a PathBuf)) -> Result<Self, Box<error::Error>> {
-        match (
-            Post::path_to_title(&path),
-            Post::path_to_last_modified(&path),
-            Post::path_to_author(&path),
-            Post::path_to_body(&path),
-        ) {
-            (Some(title), Ok(last_modified), Some(author), Ok(body)) => Ok(Post {
-                _parent: base,
-                title,
-                last_modified,
-                author,
-                checksum: Post::body_to_checksum(&body),
-                body,
-            }),
-            (None, _, _, _) => Err(err::unicode_error(&path)),
-            (_, Err(err), _, _) => Err(Box::new(err)),
-            (_, _, None, _) => Err(err::unicode_error(&path)),
-            (_, _, _, Err(err)) => Err(Box::new(err)),
+    /// Headings get an `id` derived from their text so that sections can be linked to. Repeated headings are numbered to keep the ids
+    /// unique.
+    fn anchor_headings<'a>(
+        events: Vec<Event<'a>>,
+        rendered_math: &[math::Math],
+    ) -> (Vec<Event<'a>>, Vec<Heading>) {
+        let mut anchored = Vec::with_capacity(events.len());
+        let mut headings = Vec::new();
+        let mut ids = HashSet::new();
+        let mut heading: Option<(i32, Vec<Event<'a>>)> = None;
+        for event in events {
+            heading = match (heading, event) {
+                (None, Event::Start(MarkdownTag::Header(level))) => Some((level, Vec::new())),
+                (Some((level, inner)), Event::End(MarkdownTag::Header(_))) => {
+                    let text: String = inner
+                        .iter()
+                        .filter_map(|event| match event {
+                            Event::Text(text) | Event::Code(text) => Some(&**text),
+                            _ => None,
+                        })
+                        .collect();
+                    // Math is slugified as the TeX it was written as
+                    let id = unique_id(&mut ids, &math::restore_tex(&text, rendered_math));
+                    anchored.push(Event::Html(format!("<h{} id=\"{}\">", level, id).into()));
+                    anchored.extend(inner);
+                    anchored.push(Event::Html(format!("</h{}>\n", level).into()));
+                    headings.push(Heading {
+                        level,
+                        id,
+                        html: math::restore(&ammonia::clean_text(&text), rendered_math),
+                    });
+                    None
+                }
+                (Some((level, mut inner)), event) => {
+                    inner.push(event);
+                    Some((level, inner))
+                }
+                (None, event) => {
+                    anchored.push(event);
+                    None
+                }
+            };
+        }
+        (anchored, headings)
+    }
+
+    /// Nests the headings into lists of links by level. A heading more than one level below the one before it is only nested one level
+    /// deeper, so the lists stay well formed.
+    fn headings_to_toc(headings: &[Heading]) -> String {
+        let top = headings
+            .iter()
+            .map(|heading| heading.level)
+            .min()
+            .unwrap_or(1);
+        let mut toc = String::from("<nav class=\"toc\"><b>Contents</b>");
+        let mut depth = 0;
+        headings.iter().for_each(|heading| {
+            let level = std::cmp::min((heading.level - top) as usize + 1, depth + 1);
+            if level > depth {
+                toc.push_str("<ul>");
+                depth += 1;
+            } else {
+                toc.push_str("</li>");
+                while depth > level {
+                    toc.push_str("</ul></li>");
+                    depth -= 1;
+                }
+            }
+            toc.push_str(&format!(
+                "<li><a href=\"#{}\">{}</a>",
+                heading.id, heading.html
+            ));
+        });
+        (0..depth).for_each(|_| toc.push_str("</li></ul>"));
+        toc.push_str("</nav>");
+        toc
+    }
+
+    /// Plain text of the prose, without code blocks or math
+    fn events_to_text(events: &[Event]) -> String {
+        let mut text = String::new();
+        events.iter().for_each(|event| match event {
+            Event::Text(fragment) | Event::Code(fragment) => text.push_str(fragment),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(MarkdownTag::Paragraph)
+            | Event::End(MarkdownTag::Header(_))
+            | Event::End(MarkdownTag::Item) => text.push('\n'),
+            _ => {}
+        });
+        math::strip_placeholders(&text)
+    }
+
+    fn text_to_word_count(text: &str) -> usize {
+        text.split_whitespace()
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count()
+    }
+
+    fn word_count_to_reading_minutes(word_count: usize) -> usize {
+        std::cmp::max((word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE, 1)
+    }
+
+    /// Points `post:` links at the posts they name and notes any links or images pointing at assets that don't exist. Links to posts that
+    /// can't be found are dropped, leaving just their text.
+    fn resolve_links<'a>(
+        events: Vec<Event<'a>>,
+        post_urls: &HashMap<String, String>,
+    ) -> (Vec<Event<'a>>, Vec<(String, Option<String>)>, Vec<String>) {
+        let mut resolved = Vec::with_capacity(events.len());
+        let mut post_links = Vec::new();
+        let mut missing_assets = Vec::new();
+        let mut dropping_link = false;
+        for event in events {
+            match event {
+                Event::Start(MarkdownTag::Link(link_type, dest, title)) => {
+                    if dest.starts_with(POST_LINK_SCHEME) {
+                        let post_title = percent_decode(dest[POST_LINK_SCHEME.len()..].as_bytes())
+                            .decode_utf8_lossy()
+                            .into_owned();
+                        let url = post_urls.get(&post_title).cloned();
+                        match url {
+                            Some(ref url) => resolved.push(Event::Start(MarkdownTag::Link(
+                                link_type,
+                                url.clone().into(),
+                                title,
+                            ))),
+                            None => dropping_link = true,
+                        }
+                        post_links.push((post_title, url));
+                    } else {
+                        if Post::is_missing_asset(&dest) {
+                            missing_assets.push(dest.to_string());
+                        }
+                        resolved.push(Event::Start(MarkdownTag::Link(link_type, dest, title)));
+                    }
+                }
+                Event::End(MarkdownTag::Link(..)) if dropping_link => dropping_link = false,
+                Event::Start(MarkdownTag::Image(link_type, dest, title)) => {
+                    if Post::is_missing_asset(&dest) {
+                        missing_assets.push(dest.to_string());
+                    }
+                    resolved.push(Event::Start(MarkdownTag::Image(link_type, dest, title)));
+                }
+                event => resolved.push(event),
+            }
+        }
+        (resolved, post_links, missing_assets)
+    }
+
+    /// Local images are swapped for responsive ones, using their alt text. The first image of any kind is kept for link previews.
+    fn responsive_images<'a>(
+        events: Vec<Event<'a>>,
+    ) -> (Vec<Event<'a>>, Vec<String>, Option<MetaImage>) {
+        let mut responsive = Vec::with_capacity(events.len());
+        let mut image_variants = Vec::new();
+        let mut first_image = None;
+        let mut image: Option<Vec<Event<'a>>> = None;
+        for event in events {
+            image = match (image, event) {
+                (None, Event::Start(MarkdownTag::Image(link_type, src, title))) => {
+                    Some(vec![Event::Start(MarkdownTag::Image(
+                        link_type, src, title,
+                    ))])
+                }
+                (Some(mut inner), Event::End(MarkdownTag::Image(link_type, src, title))) => {
+                    let alt: String = inner
+                        .iter()
+                        .filter_map(|event| match event {
+                            Event::Text(text) | Event::Code(text) => Some(&**text),
+                            _ => None,
+                        })
+                        .collect();
+                    let img = post_images::img(&src, &alt, &title);
+                    if first_image.is_none() {
+                        first_image = Some(
+                            match img
+                                .as_ref()
+                                .and_then(|img| resizer::preview(&src, img.width, img.height))
+                            {
+                                Some((preview_src, width, height)) => MetaImage {
+                                    src: preview_src,
+                                    alt: alt.clone(),
+                                    width: Some(width),
+                                    height: Some(height),
+                                },
+                                None => MetaImage {
+                                    src: src.to_string(),
+                                    alt: alt.clone(),
+                                    width: img.as_ref().map(|img| img.width),
+                                    height: img.as_ref().map(|img| img.height),
+                                },
+                            },
+                        );
+                    }
+                    match img {
+                        Some(img) => {
+                            responsive.push(Event::Html(img.html.into()));
+                            image_variants.extend(img.variant_urls);
+                        }
+                        None => {
+                            inner.push(Event::End(MarkdownTag::Image(link_type, src, title)));
+                            responsive.extend(inner);
+                        }
+                    }
+                    None
+                }
+                (Some(mut inner), event) => {
+                    inner.push(event);
+                    Some(inner)
+                }
+                (None, event) => {
+                    responsive.push(event);
+                    None
+                }
+            };
+        }
+        (responsive, image_variants, first_image)
+    }
+
+    fn is_missing_asset(dest: &str) -> bool {
+        dest.starts_with(ASSETS_URL_PREFIX) && {
+            let relative_path = percent_decode(dest[1..].as_bytes()).decode_utf8_lossy();
+            !Path::new(relative_path.as_ref()).exists()
+        }
+    }
+
+    fn markdown_to_body(
+        markdown_text: &str,
+        post_urls: &HashMap<String, String>,
+    ) -> RenderedMarkdown {
+        let (markdown_text, rendered_math) = math::extract(markdown_text);
+        let mut opts = Options::empty();
+        opts.insert(Options::ENABLE_FOOTNOTES);
+        opts.insert(Options::ENABLE_TABLES);
+        opts.insert(Options::ENABLE_STRIKETHROUGH);
+        opts.insert(Options::ENABLE_TASKLISTS);
+        let parser = Parser::new_ext(&markdown_text, opts);
+        let (events, headings) =
+            Post::anchor_headings(Post::highlight_code_blocks(parser), &rendered_math);
+        let (events, post_links, missing_assets) = Post::resolve_links(events, post_urls);
+        let (events, image_variants, image) = Post::responsive_images(events);
+        let text = Post::events_to_text(&events);
+        let excerpt = Post::events_to_excerpt(&events)
+            .map(|excerpt| Post::events_to_html(excerpt, &rendered_math));
+        RenderedMarkdown {
+            body: Post::events_to_html(events, &rendered_math),
+            headings,
+            text,
+            excerpt,
+            post_links,
+            missing_assets,
+            image_variants,
+            image,
+        }
+    }
+
+    /// Everything before a `<!-- more -->` marker, or else the first paragraph
+    fn events_to_excerpt<'a>(events: &[Event<'a>]) -> Option<Vec<Event<'a>>> {
+        let is_marker = |event: &Event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => html.trim() == MORE_MARKER,
+            _ => false,
+        };
+        match events.iter().position(is_marker) {
+            Some(marker) => Some(events[..marker].to_vec()),
+            None => events
+                .iter()
+                .position(|event| *event == Event::Start(MarkdownTag::Paragraph))
+                .and_then(|start| {
+                    events[start..]
+                        .iter()
+                        .position(|event| *event == Event::End(MarkdownTag::Paragraph))
+                        .map(|len| events[start..=start + len].to_vec())
+                }),
+        }
+    }
+
+    fn events_to_html(events: Vec<Event>, rendered_math: &[math::Math]) -> String {
+        let mut unsafe_html_text = String::new();
+        pulldown_cmark::html::push_html(&mut unsafe_html_text, events.into_iter());
+        sanitizer::clean(&math::restore(&unsafe_html_text, rendered_math))
+    }
+
+    fn try_from<'a>(
+        (context, path): (&'a RenderContext, &'a PathBuf),
+    ) -> Result<Self, Box<error::Error>> {
+        let post_urls = &context.post_urls;
+        // Read first, so that the file can only have changed since
+        let modified = Post::path_to_last_modified(&path)?;
+        let markdown_text = Post::path_to_markdown(&path)?;
+        let (front_matter, markdown_text) = FrontMatter::split(&markdown_text)?;
+        let (first_committed, last_modified) =
+            Post::path_to_history(&path, context.history.as_ref())?;
+        let publish_at = match front_matter.publish_at {
+            Some(ref publish_at) => Some(FrontMatter::parse_date(publish_at)?),
+            None => None,
+        };
+        let created = match front_matter.created {
+            Some(ref created) => FrontMatter::parse_date(created)?,
+            None => publish_at.or(first_committed).unwrap_or(last_modified),
+        };
+        let updated = match front_matter.updated {
+            Some(ref updated) => FrontMatter::parse_date(updated)?,
+            None => last_modified.max(created),
+        };
+        let created_from_last_modified =
+            front_matter.created.is_none() && publish_at.is_none() && first_committed.is_none();
+        match front_matter.title.or_else(|| Post::path_to_title(&path)) {
+            Some(title) => {
+                if created_from_last_modified {
+                    // The modification time changes with every checkout, so the post will move around the archive between deploys
+                    warn!(
+                        "{} has no created date, falling back to when it was last modified",
+                        title
+                    );
+                }
+                let RenderedMarkdown {
+                    body,
+                    headings,
+                    text,
+                    excerpt,
+                    post_links,
+                    missing_assets,
+                    image_variants,
+                    image,
+                } = Post::markdown_to_body(markdown_text, post_urls);
+                let summary = front_matter
+                    .summary
+                    .map(|summary| Post::markdown_to_body(&summary, post_urls));
+                let description = meta::text_to_description(
+                    summary.as_ref().map_or(&text, |summary| &summary.text),
+                );
+                let toc = match front_matter.toc {
+                    Some(false) => None,
+                    Some(true) if !headings.is_empty() => Some(Post::headings_to_toc(&headings)),
+                    None if headings.len() >= TOC_MIN_HEADINGS => {
+                        Some(Post::headings_to_toc(&headings))
+                    }
+                    _ => None,
+                };
+                let word_count = Post::text_to_word_count(&text);
+                let mut author_ids = if front_matter.authors.is_empty() {
+                    front_matter.author.into_iter().collect()
+                } else {
+                    front_matter.authors
+                };
+                if author_ids.is_empty() {
+                    author_ids.push(DEFAULT_AUTHOR_ID.to_string());
+                }
+                let base_slug = Post::to_base_slug(front_matter.slug.as_ref(), &title, &path)
+                    .ok_or_else(|| format!("Couldn't derive a slug for {:?}", path))?;
+                Ok(Post {
+                    _parent: context.parent.clone(),
+                    path: path.clone(),
+                    slug: base_slug.clone(),
+                    base_slug,
+                    title,
+                    created,
+                    updated,
+                    modified,
+                    author_ids,
+                    authors: Vec::new(),
+                    tags: front_matter
+                        .tags
+                        .iter()
+                        .map(|tag| Tag::from(tag.as_str()))
+                        .collect(),
+                    summary: summary.map(|summary| summary.body).or(excerpt),
+                    description,
+                    image,
+                    draft: front_matter.draft,
+                    publish_at,
+                    preview: false,
+                    checksum: Post::body_to_checksum(&body),
+                    toc,
+                    body,
+                    text,
+                    word_count,
+                    reading_minutes: Post::word_count_to_reading_minutes(word_count),
+                    newer: None,
+                    older: None,
+                    related: Vec::new(),
+                    post_links,
+                    missing_assets,
+                    image_variants,
+                })
+            }
+            None => Err(err::unicode_error(&path)),
         }
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    /// Loads the posts from markdown written to a fresh folder, which isn't in a repository so their history comes from the files
+    pub fn load_posts(folder_name: &str, posts: &[(&str, &str)]) -> Blog {
+        let folder = env::temp_dir().join(format!("{}-{}", folder_name, std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let mut blog = Blog::from(Arc::new(BASE.clone()));
+        let context = RenderContext {
+            parent: blog._parent.clone(),
+            post_urls: HashMap::new(),
+            history: None,
+        };
+        posts.iter().for_each(|(file_name, markdown)| {
+            let path = folder.join(file_name);
+            fs::write(&path, markdown).unwrap();
+            blog.insert_post(Post::try_from((&context, &path)).unwrap());
+        });
+        blog.reindex();
+        fs::remove_dir_all(&folder).unwrap();
+        blog
+    }
+
+    #[test]
+    fn front_matter_is_split_from_markdown() {
+        let (front_matter, markdown) = FrontMatter::split(
+            "---\ntitle: Pen Plotter\ntags: [hardware, gcode]\ndraft: true\n---\n## Introduction\n",
+        )
+        .unwrap();
+        assert_eq!(front_matter.title, Some("Pen Plotter".to_string()));
+        assert_eq!(front_matter.tags, vec!["hardware", "gcode"]);
+        assert!(front_matter.draft);
+        assert_eq!(markdown, "## Introduction\n");
+    }
+
+    #[test]
+    fn markdown_without_front_matter_is_untouched() {
+        let (front_matter, markdown) = FrontMatter::split("## Introduction\n---\n").unwrap();
+        assert_eq!(front_matter.title, None);
+        assert_eq!(markdown, "## Introduction\n---\n");
+    }
+
+    #[test]
+    fn unclosed_front_matter_is_an_error() {
+        assert!(FrontMatter::split("---\ntitle: Pen Plotter\n").is_err());
+    }
+
+    #[test]
+    fn titles_are_slugified() {
+        assert_eq!(
+            slugify("0 to Video: HDMI output on an FPGA"),
+            "0-to-video-hdmi-output-on-an-fpga"
+        );
+        assert_eq!(
+            slugify("A 2D Rendering of the Plan 9 Palette Cube"),
+            "a-2d-rendering-of-the-plan-9-palette-cube"
+        );
+        assert_eq!(slugify("  --Pen Plotter!-- "), "pen-plotter");
+    }
+
+    #[test]
+    fn repeated_ids_are_numbered() {
+        let mut ids = HashSet::new();
+        assert_eq!(unique_id(&mut ids, "Next steps"), "next-steps");
+        assert_eq!(unique_id(&mut ids, "Next Steps"), "next-steps-1");
+        assert_eq!(unique_id(&mut ids, "Next steps!"), "next-steps-2");
+        assert_eq!(unique_id(&mut ids, "???"), "section");
+    }
+
+    #[test]
+    fn colliding_slugs_are_numbered() {
+        let blog = load_posts(
+            "colliding-slugs",
+            &[
+                (
+                    "newer.md",
+                    "---\ntitle: Pen Plotter\ncreated: 2019-06-01\n---\n",
+                ),
+                (
+                    "older.md",
+                    "---\ntitle: Pen plotter!\ncreated: 2019-05-01\n---\n",
+                ),
+                (
+                    "draft.md",
+                    "---\nslug: pen-plotter\ncreated: 2019-04-01\ndraft: true\n---\n",
+                ),
+                ("lambda.md", "---\ntitle: λ\n---\n"),
+            ],
+        );
+        let slugs: Vec<(&str, &str)> = blog
+            .index
+            .iter()
+            .chain(blog.unpublished.iter())
+            .map(|post| (post.title.as_str(), post.slug.as_str()))
+            .collect();
+        assert_eq!(
+            slugs,
+            vec![
+                ("λ", "lambda"),
+                ("Pen Plotter", "pen-plotter-1"),
+                ("Pen plotter!", "pen-plotter"),
+                ("draft", "pen-plotter-2"),
+            ]
+        );
+        assert_eq!(blog.posts_by_slug.len(), 3);
+    }
+
+    #[test]
+    fn slugs_stay_clear_of_other_routes() {
+        let blog = load_posts(
+            "reserved-slugs",
+            &[
+                ("tags.md", "---\ntitle: Tags\ncreated: 2019-06-01\n---\n"),
+                (
+                    "Year in Review.md",
+                    "---\ntitle: '2019'\ncreated: 2019-05-01\n---\n",
+                ),
+            ],
+        );
+        let slugs: Vec<&str> = blog.index.iter().map(|post| post.slug.as_str()).collect();
+        assert_eq!(slugs, vec!["tags-1", "2019-1"]);
+        assert_eq!(
+            blog.redirects.get("Year in Review"),
+            Some(&"/blog/2019-1".to_string())
+        );
+        assert_eq!(blog.redirects.get("2019"), None);
+    }
+
+    #[test]
+    fn scheduled_posts_are_published_when_due() {
+        let mut blog = load_posts(
+            "scheduled-posts",
+            &[(
+                "later.md",
+                "---\ntitle: Later\npublish_at: 2999-01-01\n---\n",
+            )],
+        );
+        assert!(blog.index.is_empty());
+        assert_eq!(
+            blog.publish_due(&Utc.ymd(2998, 12, 31).and_hms(23, 59, 59)),
+            0
+        );
+        assert!(blog.posts_by_slug.get("later").is_none());
+        assert_eq!(blog.publish_due(&Utc.ymd(2999, 1, 1).and_hms(0, 0, 0)), 1);
+        assert!(blog.unpublished.is_empty());
+        assert_eq!(
+            blog.posts_by_slug
+                .get("later")
+                .map(|post| post.title.as_str()),
+            Some("Later")
+        );
+    }
+
+    #[test]
+    fn previews_need_the_post_token() {
+        let blog = load_posts(
+            "previews",
+            &[("draft.md", "---\ntitle: Draft\ndraft: true\n---\n")],
+        );
+        let post = &blog.unpublished[0];
+        let secret = "hunter2".to_string();
+        let token = post.preview_token(Some(&secret)).unwrap();
+        assert!(post.preview_token_matches(Some(&secret), &token));
+        let other_token = post.preview_token(Some(&"hunter3".to_string())).unwrap();
+        assert!(!post.preview_token_matches(Some(&secret), &other_token));
+        assert!(!post.preview_token_matches(Some(&secret), &token[1..]));
+        assert!(!post.preview_token_matches(Some(&secret), ""));
+        assert!(post.preview_token(None).is_none());
+        assert!(!post.preview_token_matches(None, &token));
+        assert!(!post.preview_token_matches(None, ""));
+    }
+
+    #[test]
+    fn authors_without_profiles_get_ids() {
+        let blog = load_posts(
+            "unregistered-authors",
+            &[(
+                "poems.md",
+                "---\ntitle: Poems\nauthors: [李白, 杜甫, Ada]\n---\n",
+            )],
+        );
+        let ids: Vec<&str> = blog.index[0]
+            .authors
+            .iter()
+            .map(|author| author.id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["author", "author-1", "ada"]);
+    }
+
+    #[test]
+    fn index_pages_hold_every_post_once() {
+        let empty = load_posts("empty-index", &[]);
+        let only_page = empty.index_page(1).unwrap();
+        assert!(only_page.posts.is_empty());
+        assert_eq!(only_page.page_count, 1);
+        assert_eq!(
+            (only_page.prev_page_url, only_page.next_page_url),
+            (None, None)
+        );
+        assert!(empty.index_page(0).is_none());
+        assert!(empty.index_page(2).is_none());
+
+        let posts: Vec<(String, String)> = (1..=INDEX_MAX_SIZE + 1)
+            .map(|day| {
+                (
+                    format!("post-{}.md", day),
+                    format!(
+                        "---\ntitle: Post {}\ncreated: 2019-05-{:02}\n---\n",
+                        day, day
+                    ),
+                )
+            })
+            .collect();
+        let posts: Vec<(&str, &str)> = posts
+            .iter()
+            .map(|(file_name, markdown)| (file_name.as_str(), markdown.as_str()))
+            .collect();
+        let blog = load_posts("paginated-index", &posts);
+        let first_page = blog.index_page(1).unwrap();
+        assert_eq!(first_page.posts.len(), INDEX_MAX_SIZE);
+        assert_eq!(
+            first_page.posts[0].title,
+            format!("Post {}", INDEX_MAX_SIZE + 1)
+        );
+        assert_eq!(first_page.prev_page_url, None);
+        assert_eq!(first_page.next_page_url, Some("/blog/page/2".to_string()));
+        let last_page = blog.index_page(2).unwrap();
+        assert_eq!(last_page.page_count, 2);
+        assert_eq!(last_page.posts.len(), 1);
+        assert_eq!(last_page.posts[0].title, "Post 1");
+        assert_eq!(last_page.prev_page_url, Some("/blog".to_string()));
+        assert_eq!(last_page.next_page_url, None);
+        assert!(blog.index_page(0).is_none());
+        assert!(blog.index_page(3).is_none());
+    }
+
+    #[test]
+    fn older_renders_do_not_replace_newer_ones() {
+        let mut blog = load_posts(
+            "stale-renders",
+            &[("plotter.md", "---\ntitle: Pen Plotter\n---\n")],
+        );
+        let mut stale = blog.index[0].clone();
+        stale.title = "Pen Plotter, half written".to_string();
+        stale.modified = Utc.ymd(2019, 5, 1).and_hms(0, 0, 0);
+        blog.replace_posts(vec![stale]);
+        assert_eq!(blog.index[0].title, "Pen Plotter");
+    }
+
+    #[test]
+    fn front_matter_urls_leave_out_unpublished_posts() {
+        let folder = env::temp_dir().join(format!("front-matter-urls-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let paths: Vec<PathBuf> = [
+            ("plotter.md", "---\ntitle: Pen Plotter\n---\n"),
+            ("draft.md", "---\ndraft: true\n---\n"),
+            ("later.md", "---\npublish_at: 2999-01-01\n---\n"),
+            ("L-Systems.md", "Lindenmayer\n"),
+        ]
+        .iter()
+        .map(|(file_name, markdown)| {
+            let path = folder.join(file_name);
+            fs::write(&path, markdown).unwrap();
+            path
+        })
+        .collect();
+        let post_urls = Blog::front_matter_urls(&paths);
+        fs::remove_dir_all(&folder).unwrap();
+        let mut expected = HashMap::new();
+        expected.insert("Pen Plotter".to_string(), "/blog/pen-plotter".to_string());
+        expected.insert("L-Systems".to_string(), "/blog/l-systems".to_string());
+        assert_eq!(post_urls, expected);
+    }
+
+    #[test]
+    fn post_pages_escape_everything_but_the_body() {
+        let blog = load_posts(
+            "escaped-posts",
+            &[(
+                "plotter.md",
+                "---\ntitle: <i>Pen</i> Plotter\ntags: [<b>]\n---\n*Plotted*\n",
+            )],
+        );
+        let html = blog.index[0].render().unwrap();
+        assert!(html.contains("<h2>&lt;i&gt;Pen&lt;&#x2f;i&gt; Plotter</h2>"));
+        assert!(html.contains("#&lt;b&gt;</a>"));
+        assert!(html.contains("<p><em>Plotted</em></p>"));
+    }
+
+    #[test]
+    fn toc_nests_headings() {
+        let heading = |level, id: &str| Heading {
+            level,
+            id: id.to_string(),
+            html: id.to_uppercase(),
+        };
+        assert_eq!(
+            Post::headings_to_toc(&[
+                heading(2, "a"),
+                heading(3, "b"),
+                heading(5, "c"),
+                heading(2, "d"),
+            ]),
+            "<nav class=\"toc\"><b>Contents</b><ul><li><a href=\"#a\">A</a><ul><li><a href=\"#b\">B</a>\
+             <ul><li><a href=\"#c\">C</a></li></ul></li></ul></li><li><a href=\"#d\">D</a></li></ul></nav>"
+        );
+    }
+
+    #[test]
+    fn headings_with_math_are_anchored_by_their_tex() {
+        let rendered = Post::markdown_to_body("## Solving $x^2$\n", &HashMap::new());
+        assert_eq!(rendered.headings[0].id, "solving-x-2");
+        assert_eq!(
+            rendered.headings[0].html,
+            "Solving&#32;<math><mrow><msup><mi>x</mi><mn>2</mn></msup></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn excerpts_end_at_the_more_marker() {
+        let excerpt = |markdown| Post::markdown_to_body(markdown, &HashMap::new()).excerpt;
+        assert_eq!(
+            excerpt("# Plotter\n\nFirst *paragraph*\n\nSecond\n"),
+            Some("<p>First <em>paragraph</em></p>\n".to_string())
+        );
+        assert_eq!(
+            excerpt("First\n\nSecond\n\n<!-- more -->\n\nThird\n"),
+            Some("<p>First</p>\n<p>Second</p>\n".to_string())
+        );
+        assert_eq!(excerpt("```rust\nfn main() {}\n```\n"), None);
+    }
+
+    #[test]
+    fn post_links_resolve_by_title() {
+        let mut post_urls = HashMap::new();
+        post_urls.insert(
+            "Lindenmayer Systems".to_string(),
+            "/blog/lindenmayer-systems".to_string(),
+        );
+        let rendered = Post::markdown_to_body(
+            "[L-systems](<post:Lindenmayer Systems>) and [plotters](post:Pen%20Plotter)\n",
+            &post_urls,
+        );
+        assert_eq!(
+            rendered.body,
+            "<p><a href=\"/blog/lindenmayer-systems\" rel=\"noopener noreferrer\">L-systems</a> and plotters</p>\n"
+        );
+        assert_eq!(
+            rendered.post_links,
+            vec![
+                (
+                    "Lindenmayer Systems".to_string(),
+                    Some("/blog/lindenmayer-systems".to_string())
+                ),
+                ("Pen Plotter".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn reading_time_counts_words_not_punctuation() {
+        let word_count = Post::text_to_word_count("Plotters — they plot!\n`G1 X10`\n");
+        assert_eq!(word_count, 5);
+        assert_eq!(Post::word_count_to_reading_minutes(word_count), 1);
+        assert_eq!(Post::word_count_to_reading_minutes(WORDS_PER_MINUTE), 1);
+        assert_eq!(Post::word_count_to_reading_minutes(WORDS_PER_MINUTE + 1), 2);
+    }
+
+    #[test]
+    fn front_matter_dates_parse() {
+        assert_eq!(
+            FrontMatter::parse_date("2019-05-01").unwrap().to_rfc3339(),
+            "2019-05-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            FrontMatter::parse_date("2019-05-01T12:30:00-04:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2019-05-01T16:30:00+00:00"
+        );
+        assert!(FrontMatter::parse_date("May 1st").is_err());
+    }
+}
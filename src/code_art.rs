@@ -1,26 +1,21 @@
 use askama::Template;
 use base::*;
 use err;
-use image::{FilterType, GenericImageView, ImageOutputFormat, ImageResult};
+use meta::{self, MetaImage, PageMeta};
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
-use rocket::State;
-use rocket::{http::ContentType, http::Status, response::Result as RocketResult, Response};
-use std::collections::HashMap;
+use resizer;
+use rocket::{http::Status, response::Redirect, State};
+use sitemap::SitemapUrl;
 use std::env;
 use std::error;
-use std::fmt;
 use std::fs;
-use std::io::Cursor;
 use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::sync::{Arc, RwLock};
 use std::thread;
 use std::time::Duration;
 
-// Sizes are all 16:9 of note (to future me) is the fact that 4K UHD/WQHD are not included; my code art images are never larger than FHD (yet), so
-// there is no reason to provide these sizes.
-type ImageSize = (u32, u32);
-const AVAILABLE_SIZES: [ImageSize; 4] = [(1920, 1080), (1280, 720), (960, 540), (640, 360)];
+const LICENSE_URL: &str = "http://creativecommons.org/licenses/by-sa/4.0/";
 
 #[derive(Template, Clone)]
 #[template(path = "code_art_gallery.html", escape = "none")]
@@ -34,41 +29,30 @@ pub fn get_index(state: State<GalleryState>) -> Gallery {
     state.read().unwrap().clone()
 }
 
-#[get("/resizer?<width>&<height>&<src>")]
+/// Images used to be resized here, so links to it (i.e. in srcsets cached elsewhere) are sent on to the shared resizer. Widths it
+/// doesn't scale down to get the original.
+#[get("/resizer?<width>&<src>")]
 pub fn get_resizer(
     gallery_state: State<GalleryState>,
     width: u32,
-    height: u32,
     src: String,
-) -> RocketResult {
-    let size_tuple = (width, height);
-    if AVAILABLE_SIZES.iter().any(|size| size == &size_tuple) {
-        let gallery_state = gallery_state.read().unwrap();
-        gallery_state
-            .images
-            .iter()
-            .find(|img| img.src == src)
-            .map_or(Err(Status::NotFound), |img| {
-                img.size_to_image_bytes.get(&size_tuple).map_or(
-                    Err(Status::InternalServerError),
-                    |resized_image_bytes| {
-                        Ok(Response::build()
-                            .header(ContentType::PNG)
-                            .sized_body(Cursor::new(resized_image_bytes.clone()))
-                            .finalize())
-                    },
-                )
-            })
-    }
-    // This is the ideal response code here. The query is valid and well formed but it will not be processed because it doesn't match the
-    // expectation that it should be one of AVAILABLE_SIZES. If this happens, in all likelihood, someone is just messing around with the query.
-    else {
-        Err(Status::UnprocessableEntity)
-    }
+) -> Result<Redirect, Status> {
+    let gallery = gallery_state.read().unwrap();
+    let image = gallery
+        .images
+        .iter()
+        .find(|img| img.src == src)
+        .ok_or(Status::NotFound)?;
+    Ok(Redirect::permanent(
+        resizer::variant_urls(&image.src, image.width)
+            .into_iter()
+            .find(|&(variant_width, _)| variant_width == width)
+            .map_or_else(|| image.src.clone(), |(_, url)| url),
+    ))
 }
 
 const FOLDER_PATH: &str = "./files/code_art";
-type GalleryState = Arc<RwLock<Gallery>>;
+pub type GalleryState = Arc<RwLock<Gallery>>;
 impl Gallery {
     fn remove_image(&mut self, path_to_remove: &PathBuf) -> Result<Image, Box<error::Error>> {
         Image::path_to_src(&path_to_remove).and_then(|src_to_remove| {
@@ -118,6 +102,60 @@ impl Gallery {
         gallery
     }
 
+    /// Loads every image up front without watching for changes, for when the gallery has to be complete right away (i.e. exporting)
+    pub fn load(parent: Arc<Base>) -> GalleryState {
+        let mut gallery = Gallery::from(parent);
+        gallery.initialize();
+        Arc::new(RwLock::new(gallery))
+    }
+
+    fn meta(&self) -> PageMeta {
+        let images: Vec<MetaImage> = self.images.iter().map(Image::to_meta_image).collect();
+        PageMeta {
+            image: images.first().cloned(),
+            structured_data: meta::to_script_json(&json!({
+                "@context": "https://schema.org",
+                "@type": "ImageGallery",
+                "name": "Code Art Gallery",
+                "url": format!("{}/code_art", *PUBLIC_URL),
+                "image": images
+                    .iter()
+                    .map(|image| {
+                        let mut image_object = image.to_image_object();
+                        image_object["name"] = json!(image.alt);
+                        image_object["license"] = json!(LICENSE_URL);
+                        image_object
+                    })
+                    .collect::<Vec<_>>(),
+            })),
+            ..PageMeta::website(
+                "ImageGallery",
+                "Code Art Gallery",
+                "Art made with code, free to share under CC BY-SA 4.0.",
+                "/code_art",
+            )
+        }
+    }
+
+    /// The gallery and every resized image in it, for exporting the site
+    pub fn export_urls(&self) -> Vec<String> {
+        std::iter::once("/code_art".to_string())
+            .chain(self.images.iter().flat_map(|img| {
+                resizer::variant_urls(&img.src, img.width)
+                    .into_iter()
+                    .map(|(_, url)| url)
+            }))
+            .collect()
+    }
+
+    /// Images are listed at their preview size, since the originals are under /files where crawlers aren't allowed
+    pub fn sitemap_urls(&self) -> Vec<SitemapUrl> {
+        vec![SitemapUrl {
+            images: self.images.iter().map(Image::to_meta_image).collect(),
+            ..SitemapUrl::new("/code_art", None)
+        }]
+    }
+
     fn spawn_updater(gallery_state: GalleryState) {
         thread::spawn(move || {
             gallery_state.write().unwrap().initialize();
@@ -190,33 +228,15 @@ impl From<Arc<Base>> for Gallery {
     }
 }
 
-#[derive(Deserialize, Serialize)]
-pub struct Resize {
-    width: u32,
-    height: u32,
-    src: String,
-}
-
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 struct Image {
     name: String,
     href: String,
     srcset: String,
     src: String,
     desc: String,
-    size_to_image_bytes: HashMap<ImageSize, Vec<u8>>,
-}
-
-// TODO: this adds overhead that I feel could be avoided if a wrapper was provided for the size_to_image_bytes field. The wrapper itself would
-// implement fmt::Debug and just show something like "not shown" (wording is debatable)
-impl fmt::Debug for Image {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Image {{ name: {:?} href: {:?} src: {:?} desc: {:?} }}",
-            self.name, self.href, self.src, self.desc
-        )
-    }
+    width: u32,
+    height: u32,
 }
 
 impl Image {
@@ -239,24 +259,15 @@ impl Image {
         }
     }
 
-    fn src_to_srcset(src: &str) -> String {
-        // AVAILABLE_SIZES
-        AVAILABLE_SIZES
-            .iter()
-            .map(|size| Resize {
-                width: size.0,
-                height: size.1,
-                src: src.to_string(),
-            })
-            .map(|resize| {
-                format!(
-                    "/code_art/resizer?{} {}w",
-                    serde_urlencoded::to_string(&resize).unwrap(),
-                    resize.width,
-                )
-            })
-            .collect::<Vec<String>>()
-            .join(", ")
+    fn to_meta_image(&self) -> MetaImage {
+        let (src, width, height) = resizer::preview(&self.src, self.width, self.height)
+            .unwrap_or_else(|| (self.src.clone(), self.width, self.height));
+        MetaImage {
+            src,
+            alt: self.name.clone(),
+            width: Some(width),
+            height: Some(height),
+        }
     }
 
     // Adds a space before uppercase letters excluding the first. 'CamelCaseName' --> 'Camel Case Name'
@@ -280,45 +291,21 @@ impl Image {
         Some(String::new())
     }
 
-    fn path_to_resized_image_bytes(path: &PathBuf) -> ImageResult<HashMap<ImageSize, Vec<u8>>> {
-        image::open(path).and_then(|dynamic_image| {
-            let mut size_to_image_bytes = HashMap::new();
-            AVAILABLE_SIZES
-                .iter()
-                .try_for_each(|size| {
-                    let resized_dynamic_image = if dynamic_image.dimensions() == *size {
-                        dynamic_image.clone()
-                    } else {
-                        dynamic_image.resize_exact(size.0, size.1, FilterType::Nearest)
-                    };
-                    let mut resized_image_bytes = Vec::new();
-                    // If this fails, indicates that png_codec is unavailable, in which case
-                    let write_result = resized_dynamic_image
-                        .write_to(&mut resized_image_bytes, ImageOutputFormat::PNG);
-
-                    if write_result.is_ok() {
-                        size_to_image_bytes.insert(size.clone(), resized_image_bytes);
-                    }
-                    write_result
-                })
-                .map(|_| size_to_image_bytes)
-        })
-    }
-
     fn try_from(path: &PathBuf) -> Result<Self, Box<error::Error>> {
         match (
             Image::path_to_src(path),
             Image::path_to_name(path),
             Image::path_to_desc(path),
-            Image::path_to_resized_image_bytes(path),
+            image::image_dimensions(path),
         ) {
-            (Ok(src), Some(name), Some(desc), Ok(size_to_image_bytes)) => Ok(Image {
+            (Ok(src), Some(name), Some(desc), Ok((width, height))) => Ok(Image {
                 href: src.clone(),
-                srcset: Image::src_to_srcset(&src),
+                srcset: resizer::srcset(&src, width),
                 src,
                 name,
                 desc,
-                size_to_image_bytes,
+                width,
+                height,
             }),
             (Err(err), _, _, _) => Err(err),
             (Ok(_), _, _, Err(err)) => Err(Box::new(err)),
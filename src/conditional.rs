@@ -0,0 +1,147 @@
+//! Conditional GET. Pages are sent with an `ETag` and `Last-Modified`, and a client that already has the current version gets a bodiless
+//! `304 Not Modified` instead.
+
+use chrono::{DateTime, Utc};
+use rocket::http::{Header, Status};
+use rocket::request::{self, FromRequest, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::Outcome;
+use std::io::Cursor;
+
+/// Browsers may reuse a page for a few minutes before checking back, which is cheap thanks to the 304s
+pub const PUBLIC: &str = "public, max-age=300";
+
+/// For anything that mustn't end up in a shared cache, like post previews
+pub const PRIVATE: &str = "private, no-store";
+
+/// HTTP dates are always in GMT
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// What the client already has, from its request headers
+pub struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<DateTime<Utc>>,
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for Conditions {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        let headers = request.headers();
+        Outcome::Success(Conditions {
+            if_none_match: headers.get_one("If-None-Match").map(str::to_string),
+            if_modified_since: headers
+                .get_one("If-Modified-Since")
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+        })
+    }
+}
+
+impl Conditions {
+    /// If-None-Match wins over If-Modified-Since when both are sent, as RFC 7232 asks
+    fn is_fresh(&self, etag: &str, last_modified: &DateTime<Utc>) -> bool {
+        match (&self.if_none_match, &self.if_modified_since) {
+            (Some(if_none_match), _) => if_none_match.split(',').any(|candidate| {
+                let candidate = candidate.trim();
+                candidate == "*" || candidate.trim_start_matches("W/") == etag
+            }),
+            // HTTP dates have no fractional seconds
+            (None, Some(if_modified_since)) => {
+                last_modified.timestamp() <= if_modified_since.timestamp()
+            }
+            (None, None) => false,
+        }
+    }
+}
+
+/// A strong entity tag made by hashing the body, so that it changes with anything the page depends on (i.e. templates) and not just
+/// with what the page says
+fn etag(body: &[u8]) -> String {
+    format!(
+        "\"{}\"",
+        base64::encode_config(&openssl::sha::sha256(body), base64::URL_SAFE_NO_PAD)
+    )
+}
+
+/// Renders `inner` even when the client's copy turns out to be fresh, since the ETag comes from the rendered body
+pub struct Cached<R> {
+    pub inner: R,
+    pub conditions: Conditions,
+    pub last_modified: DateTime<Utc>,
+    pub cache_control: &'static str,
+}
+
+impl<'r, R: Responder<'r>> Responder<'r> for Cached<R> {
+    fn respond_to(self, request: &Request) -> response::Result<'r> {
+        let Cached {
+            inner,
+            conditions,
+            last_modified,
+            cache_control,
+        } = self;
+        let mut response = inner.respond_to(request)?;
+        let body = response.body_bytes().unwrap_or_default();
+        let etag = etag(&body);
+        if conditions.is_fresh(&etag, &last_modified) {
+            response = Response::build().status(Status::NotModified).finalize();
+        } else {
+            response.set_sized_body(Cursor::new(body));
+        }
+        response.set_header(Header::new(
+            "Last-Modified",
+            last_modified.format(HTTP_DATE_FORMAT).to_string(),
+        ));
+        response.set_header(Header::new("ETag", etag));
+        response.set_header(Header::new("Cache-Control", cache_control));
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn conditions(if_none_match: Option<&str>, if_modified_since: Option<&str>) -> Conditions {
+        Conditions {
+            if_none_match: if_none_match.map(str::to_string),
+            if_modified_since: if_modified_since.map(|date| {
+                DateTime::parse_from_rfc2822(date)
+                    .unwrap()
+                    .with_timezone(&Utc)
+            }),
+        }
+    }
+
+    #[test]
+    fn matching_etags_are_fresh() {
+        let etag = etag(b"body");
+        let last_modified = Utc.ymd(2019, 5, 1).and_hms(12, 0, 0);
+        assert!(
+            conditions(Some(&format!("\"x\", W/{}", etag)), None).is_fresh(&etag, &last_modified)
+        );
+        assert!(
+            !conditions(Some("\"x\""), Some("Wed, 01 May 2019 12:00:00 GMT"))
+                .is_fresh(&etag, &last_modified)
+        );
+    }
+
+    #[test]
+    fn unmodified_dates_are_fresh() {
+        let last_modified = Utc.ymd(2019, 5, 1).and_hms_milli(12, 0, 0, 500);
+        assert!(
+            conditions(None, Some("Wed, 01 May 2019 12:00:00 GMT")).is_fresh("", &last_modified)
+        );
+        assert!(
+            !conditions(None, Some("Wed, 01 May 2019 11:59:59 GMT")).is_fresh("", &last_modified)
+        );
+        assert!(!conditions(None, None).is_fresh("", &last_modified));
+    }
+
+    #[test]
+    fn etags_follow_the_body() {
+        assert_eq!(etag(b"<p>Pen Plotter</p>"), etag(b"<p>Pen Plotter</p>"));
+        assert_ne!(etag(b"<p>Pen Plotter</p>"), etag(b"<p>Pen plotter</p>"));
+    }
+}
@@ -0,0 +1,136 @@
+//! Renders the whole site to static files by requesting every page from a local instance of it, so that the output is exactly what the
+//! server would send. Pages are written to an `index.html` in a folder named after them, so links without extensions keep working on
+//! plain static hosting. Static hosts ignore query strings, so every page that is exported (later index pages, resized images) is
+//! addressed by its path alone.
+
+use blog::BlogState;
+use code_art::GalleryState;
+use rocket::http::Status;
+use rocket::local::Client;
+use rocket::Rocket;
+use std::collections::HashSet;
+use std::error;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use url::percent_encoding::percent_decode;
+
+/// Served as-is rather than rendered
+const FILES_PATH: &str = "./files";
+
+pub fn export(rocket: Rocket, outdir: &Path) -> Result<(), Box<error::Error>> {
+    let mut urls: Vec<String> = vec!["/", "/about", "/robots.txt", "/sitemap.xml"]
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+    urls.extend(
+        rocket
+            .state::<BlogState>()
+            .ok_or("Blog state is not managed")?
+            .read()
+            .unwrap()
+            .export_urls(),
+    );
+    urls.extend(
+        rocket
+            .state::<GalleryState>()
+            .ok_or("Gallery state is not managed")?
+            .read()
+            .unwrap()
+            .export_urls(),
+    );
+
+    let client = Client::new(rocket).map_err(|err| err.to_string())?;
+    let mut exported = HashSet::new();
+    for url in urls {
+        if !exported.insert(url.clone()) {
+            continue;
+        }
+        let path = match url_to_path(&url) {
+            Some(path) => outdir.join(path),
+            None => {
+                warn!("Skipping {}, it can't be written under {:?}", url, outdir);
+                continue;
+            }
+        };
+        let mut response = client.get(url.as_str()).dispatch();
+        if response.status() != Status::Ok {
+            warn!("Skipping {}, got {}", url, response.status());
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, response.body_bytes().unwrap_or_default())?;
+        debug!("Exported {} to {:?}", url, path);
+    }
+    copy_dir(Path::new(FILES_PATH), &outdir.join("files"))?;
+    info!("Exported {} pages to {:?}", exported.len(), outdir);
+    Ok(())
+}
+
+/// Where a URL is written, relative to the output directory. None for anything that would escape it, or that has a query.
+fn url_to_path(url: &str) -> Option<PathBuf> {
+    if url.contains('?') {
+        return None;
+    }
+    let path = PathBuf::from(
+        percent_decode(url.trim_start_matches('/').as_bytes())
+            .decode_utf8_lossy()
+            .as_ref(),
+    );
+    if !path.components().all(|component| match component {
+        Component::Normal(_) => true,
+        _ => false,
+    }) {
+        return None;
+    }
+    Some(if path.extension().is_some() {
+        path
+    } else {
+        path.join("index.html")
+    })
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<(), Box<error::Error>> {
+    fs::create_dir_all(to)?;
+    for dir_entry in fs::read_dir(from)? {
+        let dir_entry = dir_entry?;
+        let to = to.join(dir_entry.file_name());
+        if dir_entry.file_type()?.is_dir() {
+            copy_dir(&dir_entry.path(), &to)?;
+        } else {
+            fs::copy(dir_entry.path(), to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls_map_to_files() {
+        let path = |url| url_to_path(url).map(|path| path.to_string_lossy().into_owned());
+        assert_eq!(path("/"), Some("index.html".to_string()));
+        assert_eq!(
+            path("/blog/pen-plotter"),
+            Some("blog/pen-plotter/index.html".to_string())
+        );
+        assert_eq!(path("/blog/feed.xml"), Some("blog/feed.xml".to_string()));
+        assert_eq!(
+            path("/blog/page/2"),
+            Some("blog/page/2/index.html".to_string())
+        );
+        assert_eq!(
+            path("/resized/640/plotter.jpg"),
+            Some("resized/640/plotter.jpg".to_string())
+        );
+        assert_eq!(path("/blog/search?q=fpga"), None);
+        assert_eq!(
+            path("/blog/tag/c%2B%2B"),
+            Some("blog/tag/c++/index.html".to_string())
+        );
+        assert_eq!(path("/blog/tag/%2E%2E"), None);
+    }
+}
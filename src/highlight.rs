@@ -0,0 +1,404 @@
+//! Server-side syntax highlighting for fenced code blocks in blog posts. This is a plain lexer rather than a real parser: it only knows
+//! enough about each language to pick out comments, strings, numbers, keywords and types, which is all the stylesheet colors anyway.
+//!
+//! Highlighted tokens are wrapped in `<span class="hl-*">` so that no inline styles need to make it past the sanitizer.
+
+use base::push_escaped;
+
+struct Language {
+    /// What the fence may say after the backticks, lowercase
+    names: &'static [&'static str],
+    /// Class of the `code` element
+    class: &'static str,
+    keywords: &'static [&'static str],
+    types: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    string_delimiters: &'static [char],
+    case_sensitive: bool,
+}
+
+static LANGUAGES: [Language; 6] = [
+    Language {
+        names: &["rust", "rs"],
+        class: "language-rust",
+        keywords: &[
+            "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false", "fn",
+            "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+            "return", "self", "Self", "static", "struct", "super", "trait", "true", "type",
+            "unsafe", "use", "where", "while", "dyn",
+        ],
+        types: &[
+            "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32",
+            "i64", "i128", "isize", "f32", "f64", "String", "Vec", "Option", "Result", "Box",
+            "Some", "None", "Ok", "Err",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        string_delimiters: &['"'],
+        case_sensitive: true,
+    },
+    Language {
+        names: &["verilog", "v", "systemverilog", "sv"],
+        class: "language-verilog",
+        keywords: &[
+            "always",
+            "assign",
+            "begin",
+            "case",
+            "casex",
+            "casez",
+            "default",
+            "else",
+            "end",
+            "endcase",
+            "endfunction",
+            "endgenerate",
+            "endmodule",
+            "for",
+            "function",
+            "generate",
+            "genvar",
+            "if",
+            "initial",
+            "localparam",
+            "module",
+            "negedge",
+            "parameter",
+            "posedge",
+            "or",
+            "and",
+            "not",
+        ],
+        types: &[
+            "input", "output", "inout", "reg", "wire", "integer", "logic", "signed", "unsigned",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        string_delimiters: &['"'],
+        case_sensitive: true,
+    },
+    Language {
+        names: &["go", "golang"],
+        class: "language-go",
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "fallthrough",
+            "for",
+            "func",
+            "go",
+            "goto",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "type",
+            "var",
+            "true",
+            "false",
+            "nil",
+        ],
+        types: &[
+            "bool",
+            "byte",
+            "complex64",
+            "complex128",
+            "error",
+            "float32",
+            "float64",
+            "int",
+            "int8",
+            "int16",
+            "int32",
+            "int64",
+            "rune",
+            "string",
+            "uint",
+            "uint8",
+            "uint16",
+            "uint32",
+            "uint64",
+            "uintptr",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        string_delimiters: &['"', '`', '\''],
+        case_sensitive: true,
+    },
+    Language {
+        names: &["python", "py"],
+        class: "language-python",
+        keywords: &[
+            "and", "as", "assert", "break", "class", "continue", "def", "del", "elif", "else",
+            "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda",
+            "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with", "yield",
+            "True", "False", "None",
+        ],
+        types: &[
+            "bool", "bytes", "dict", "float", "int", "list", "object", "set", "str", "tuple",
+        ],
+        line_comment: Some("#"),
+        block_comment: None,
+        string_delimiters: &['"', '\''],
+        case_sensitive: true,
+    },
+    Language {
+        names: &["gcode", "g-code", "nc"],
+        class: "language-gcode",
+        keywords: &[
+            "g0", "g00", "g1", "g01", "g2", "g02", "g3", "g03", "g4", "g04", "g17", "g18", "g19",
+            "g20", "g21", "g28", "g90", "g91", "g92", "m0", "m00", "m2", "m02", "m3", "m03", "m4",
+            "m04", "m5", "m05", "m30",
+        ],
+        types: &[],
+        line_comment: Some(";"),
+        block_comment: Some(("(", ")")),
+        string_delimiters: &[],
+        case_sensitive: false,
+    },
+    Language {
+        names: &["c", "cpp", "c++", "cxx", "h"],
+        class: "language-c",
+        keywords: &[
+            "break",
+            "case",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "enum",
+            "extern",
+            "for",
+            "goto",
+            "if",
+            "namespace",
+            "new",
+            "private",
+            "protected",
+            "public",
+            "return",
+            "sizeof",
+            "static",
+            "struct",
+            "switch",
+            "template",
+            "this",
+            "typedef",
+            "union",
+            "using",
+            "virtual",
+            "volatile",
+            "while",
+            "true",
+            "false",
+            "nullptr",
+            "NULL",
+        ],
+        types: &[
+            "auto", "bool", "char", "double", "float", "int", "long", "short", "signed",
+            "unsigned", "void", "size_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t", "int8_t",
+            "int16_t", "int32_t", "int64_t",
+        ],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        string_delimiters: &['"', '\''],
+        case_sensitive: true,
+    },
+];
+
+/// Every class a highlighted span can have, for the sanitizer whitelist
+pub const SPAN_CLASSES: [&str; 5] = [
+    "hl-comment",
+    "hl-string",
+    "hl-number",
+    "hl-keyword",
+    "hl-type",
+];
+
+/// Every class a highlighted `code` element can have, for the sanitizer whitelist
+pub fn code_classes() -> Vec<&'static str> {
+    LANGUAGES.iter().map(|language| language.class).collect()
+}
+
+impl Language {
+    fn by_name(name: &str) -> Option<&'static Language> {
+        let name = name.to_lowercase();
+        LANGUAGES
+            .iter()
+            .find(|language| language.names.contains(&name.as_str()))
+    }
+
+    fn classify_word(&self, word: &str) -> Option<&'static str> {
+        let matches = |candidate: &&str| {
+            if self.case_sensitive {
+                *candidate == word
+            } else {
+                candidate.eq_ignore_ascii_case(word)
+            }
+        };
+        if self.keywords.iter().any(matches) {
+            Some("hl-keyword")
+        } else if self.types.iter().any(matches) {
+            Some("hl-type")
+        } else {
+            None
+        }
+    }
+
+    /// Splits the next token off the front of `code`, returning its length in bytes and its class, if it has one.
+    fn next_token(&self, code: &str) -> (usize, Option<&'static str>) {
+        let first = match code.chars().next() {
+            Some(first) => first,
+            None => return (0, None),
+        };
+        if let Some(line_comment) = self.line_comment {
+            if code.starts_with(line_comment) {
+                return (
+                    code.find('\n').unwrap_or_else(|| code.len()),
+                    Some("hl-comment"),
+                );
+            }
+        }
+        if let Some((open, close)) = self.block_comment {
+            if code.starts_with(open) {
+                let len = code[open.len()..]
+                    .find(close)
+                    .map(|end| open.len() + end + close.len())
+                    .unwrap_or_else(|| code.len());
+                return (len, Some("hl-comment"));
+            }
+        }
+        if self.string_delimiters.contains(&first) {
+            let mut escaped = false;
+            let len = code
+                .char_indices()
+                .skip(1)
+                .find(|&(_, c)| {
+                    let closes = !escaped && c == first;
+                    escaped = !escaped && c == '\\';
+                    closes
+                })
+                .map(|(i, c)| i + c.len_utf8())
+                .unwrap_or_else(|| code.len());
+            return (len, Some("hl-string"));
+        }
+        let word_len = |is_word_char: &Fn(char) -> bool| {
+            code.char_indices()
+                .find(|&(_, c)| !is_word_char(c))
+                .map(|(i, _)| i)
+                .unwrap_or_else(|| code.len())
+        };
+        if first.is_ascii_digit() {
+            // Loose enough for 1.5e3, 0xFF, 1_000u32 and Verilog's 8'hFF
+            let len = word_len(&|c: char| c.is_alphanumeric() || c == '_' || c == '.' || c == '\'');
+            return (len, Some("hl-number"));
+        }
+        if first.is_alphabetic() || first == '_' || first == '$' {
+            let len = word_len(&|c: char| c.is_alphanumeric() || c == '_' || c == '$');
+            return (len, self.classify_word(&code[..len]));
+        }
+        (first.len_utf8(), None)
+    }
+}
+
+fn highlight(language: &Language, code: &str) -> String {
+    let mut html = String::with_capacity(code.len() * 2);
+    let mut rest = code;
+    while !rest.is_empty() {
+        let (len, class) = language.next_token(rest);
+        let (token, remainder) = rest.split_at(len);
+        match class {
+            Some(class) => {
+                html.push_str("<span class=\"");
+                html.push_str(class);
+                html.push_str("\">");
+                push_escaped(&mut html, token);
+                html.push_str("</span>");
+            }
+            None => push_escaped(&mut html, token),
+        }
+        rest = remainder;
+    }
+    html
+}
+
+/// Renders a fenced code block. `info` is whatever followed the opening fence; its first word picks the language. Blocks in languages
+/// that aren't known here are escaped but otherwise left plain.
+pub fn code_block(info: &str, code: &str) -> String {
+    let mut html = String::new();
+    match info.split_whitespace().next().and_then(Language::by_name) {
+        Some(language) => {
+            html.push_str("<pre><code class=\"");
+            html.push_str(language.class);
+            html.push_str("\">");
+            html.push_str(&highlight(language, code));
+        }
+        None => {
+            html.push_str("<pre><code>");
+            push_escaped(&mut html, code);
+        }
+    }
+    html.push_str("</code></pre>\n");
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rust_is_highlighted() {
+        assert_eq!(
+            code_block("rust", "let x = 5; // five\n"),
+            "<pre><code class=\"language-rust\"><span class=\"hl-keyword\">let</span> x = \
+             <span class=\"hl-number\">5</span>; <span class=\"hl-comment\">// five</span>\n\
+             </code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn strings_are_escaped_and_respect_escapes() {
+        assert_eq!(
+            code_block("python", r#"print("<\"b\">")"#),
+            "<pre><code class=\"language-python\">print(<span class=\"hl-string\">\
+             &quot;&lt;\\&quot;b\\&quot;&gt;&quot;</span>)</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn gcode_is_case_insensitive() {
+        assert_eq!(
+            code_block("gcode", "g1 X10 (move)"),
+            "<pre><code class=\"language-gcode\"><span class=\"hl-keyword\">g1</span> X10 \
+             <span class=\"hl-comment\">(move)</span></code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn unknown_languages_are_plain() {
+        assert_eq!(
+            code_block("", "a < b"),
+            "<pre><code>a &lt; b</code></pre>\n"
+        );
+        assert_eq!(
+            code_block("brainfuck", "+[-]"),
+            "<pre><code>+[-]</code></pre>\n"
+        );
+    }
+}
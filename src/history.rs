@@ -0,0 +1,187 @@
+//! When files were created and last changed according to git, which unlike their modification times survives a fresh clone. The
+//! repository is read directly rather than by running git, so nothing needs to be installed alongside the server.
+
+use chrono::{DateTime, TimeZone, Utc};
+use git2::{Commit, Delta, Diff, DiffOptions, Repository, StatusOptions};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+pub struct FileHistory {
+    /// When the file was first committed
+    pub created: DateTime<Utc>,
+    /// When the file was last committed, or None if it has changed since
+    pub updated: Option<DateTime<Utc>>,
+}
+
+/// The history of every file in a folder, read with a single walk over the commits
+pub struct History {
+    workdir: PathBuf,
+    /// Times of the first and last commits to change each file, by path relative to the working directory
+    commit_times: HashMap<PathBuf, (i64, i64)>,
+    /// Files changed since they were last committed
+    uncommitted: HashSet<PathBuf>,
+}
+
+impl History {
+    /// Fails if the folder isn't in a repository or the repository has no commits
+    pub fn load(folder: &Path) -> Result<History, git2::Error> {
+        let repo = Repository::discover(folder)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("Repository is bare"))?
+            .canonicalize()
+            .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+        let canonical_folder = folder
+            .canonicalize()
+            .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+        let relative_folder = canonical_folder
+            .strip_prefix(&workdir)
+            .map_err(|err| git2::Error::from_str(&err.to_string()))?;
+        let mut diff_options = DiffOptions::new();
+        let mut status_options = StatusOptions::new();
+        if relative_folder != Path::new("") {
+            diff_options.pathspec(relative_folder);
+            status_options.pathspec(relative_folder);
+        }
+
+        let mut revwalk = repo.revwalk()?;
+        revwalk.push_head()?;
+        let mut commit_times: HashMap<PathBuf, (i64, i64)> = HashMap::new();
+        for oid in revwalk {
+            let commit = repo.find_commit(oid?)?;
+            let time = commit.time().seconds();
+            for path in changed_paths(&repo, &commit, &mut diff_options)? {
+                let times = commit_times.entry(path).or_insert((time, time));
+                *times = (times.0.min(time), times.1.max(time));
+            }
+        }
+        let uncommitted = repo
+            .statuses(Some(&mut status_options))?
+            .iter()
+            .filter_map(|entry| entry.path().map(PathBuf::from))
+            .collect();
+        Ok(History {
+            workdir,
+            commit_times,
+            uncommitted,
+        })
+    }
+
+    /// None if the file has never been committed. Renames aren't followed, so a renamed file is as old as the rename.
+    pub fn file_history(&self, path: &Path) -> Option<FileHistory> {
+        let canonical_path = path.canonicalize().ok()?;
+        let relative_path = canonical_path.strip_prefix(&self.workdir).ok()?;
+        self.commit_times
+            .get(relative_path)
+            .map(|&(first, last)| FileHistory {
+                created: Utc.timestamp(first, 0),
+                updated: if self.uncommitted.contains(relative_path) {
+                    None
+                } else {
+                    Some(Utc.timestamp(last, 0))
+                },
+            })
+    }
+}
+
+/// Files the commit added or changed. Merges only count files that differ from every parent, the same as `git log` does.
+fn changed_paths(
+    repo: &Repository,
+    commit: &Commit,
+    diff_options: &mut DiffOptions,
+) -> Result<HashSet<PathBuf>, git2::Error> {
+    let tree = commit.tree()?;
+    if commit.parent_count() == 0 {
+        return Ok(diff_to_paths(&repo.diff_tree_to_tree(
+            None,
+            Some(&tree),
+            Some(diff_options),
+        )?));
+    }
+    let mut changed: Option<HashSet<PathBuf>> = None;
+    for parent in commit.parents() {
+        let paths = diff_to_paths(&repo.diff_tree_to_tree(
+            Some(&parent.tree()?),
+            Some(&tree),
+            Some(diff_options),
+        )?);
+        changed = Some(match changed {
+            Some(changed) => changed.intersection(&paths).cloned().collect(),
+            None => paths,
+        });
+    }
+    Ok(changed.unwrap_or_default())
+}
+
+fn diff_to_paths(diff: &Diff) -> HashSet<PathBuf> {
+    diff.deltas()
+        .filter(|delta| delta.status() != Delta::Deleted)
+        .filter_map(|delta| delta.new_file().path().map(Path::to_path_buf))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use git2::{IndexAddOption, Signature, Time};
+    use std::env;
+    use std::fs;
+
+    fn commit(repo: &Repository, seconds: i64) {
+        let mut index = repo.index().unwrap();
+        index
+            .add_all(["*"].iter(), IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature =
+            Signature::new("Sameer", "sameer@example.com", &Time::new(seconds, 0)).unwrap();
+        let parent = repo
+            .head()
+            .ok()
+            .and_then(|head| head.target())
+            .map(|oid| repo.find_commit(oid).unwrap());
+        let parents: Vec<&Commit> = parent.iter().collect();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Update",
+            &tree,
+            &parents,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn history_comes_from_commits() {
+        let workdir = env::temp_dir().join(format!("history-{}", std::process::id()));
+        let folder = workdir.join("blog");
+        fs::create_dir_all(&folder).unwrap();
+        fs::create_dir_all(workdir.join("files")).unwrap();
+        let repo = Repository::init(&workdir).unwrap();
+        fs::write(folder.join("plotter.md"), "Pen Plotter").unwrap();
+        fs::write(workdir.join("files/style.css"), "").unwrap();
+        commit(&repo, 1000);
+        fs::write(folder.join("plotter.md"), "Pen Plotter, finished").unwrap();
+        fs::write(folder.join("lattice.md"), "Lattice Points").unwrap();
+        commit(&repo, 2000);
+        fs::write(folder.join("lattice.md"), "Lattice Points, half written").unwrap();
+
+        let history = History::load(&folder).unwrap();
+        let times = |file_name: &str| {
+            history
+                .file_history(&folder.join(file_name))
+                .map(|file_history| {
+                    (
+                        file_history.created.timestamp(),
+                        file_history.updated.map(|updated| updated.timestamp()),
+                    )
+                })
+        };
+        assert_eq!(times("plotter.md"), Some((1000, Some(2000))));
+        assert_eq!(times("lattice.md"), Some((2000, None)));
+        assert_eq!(times("../files/style.css"), None);
+        fs::remove_dir_all(&workdir).unwrap();
+    }
+}
@@ -7,6 +7,8 @@ extern crate openssl;
 extern crate log;
 extern crate base64;
 extern crate env_logger;
+extern crate git2;
+extern crate html5ever;
 extern crate notify;
 #[macro_use]
 extern crate serde_derive;
@@ -17,21 +19,39 @@ extern crate pulldown_cmark;
 extern crate regex;
 extern crate reqwest;
 extern crate rocket_contrib;
+extern crate rust_stemmers;
+#[macro_use]
+extern crate serde_json;
 extern crate serde_urlencoded;
+extern crate serde_yaml;
 extern crate url;
 #[macro_use]
 extern crate lazy_static;
 
-use rocket::Config;
+use rocket::{Config, Rocket};
 use std::env;
+use std::path::Path;
+use std::process;
 use std::sync::Arc;
 
+mod authors;
 mod base;
 use base::*;
 mod blog;
 mod code_art;
+mod conditional;
 mod err;
+mod export;
+mod highlight;
+mod history;
+mod math;
+mod meta;
+mod post_images;
+mod resizer;
 mod robots;
+mod sanitizer;
+mod search;
+mod sitemap;
 mod static_pages;
 mod youtube;
 
@@ -45,24 +65,34 @@ fn configure() -> Config {
     config
 }
 
-fn main() {
-    if env::var("RUST_LOG").is_err() {
-        env::set_var("RUST_LOG", "info,spuri_io=debug");
-    }
-    env_logger::init();
-    info!("Starting...");
-
-    let base_arc = Arc::new(BASE.clone());
-
+fn rocket(blog: blog::BlogState, gallery: code_art::GalleryState) -> Rocket {
     rocket::custom(configure())
         .manage(BASE.clone())
-        .manage(blog::Blog::new(base_arc.clone()))
-        .manage(code_art::Gallery::new(base_arc.clone()))
-        .mount("/blog", routes![blog::get_index, blog::get_post])
+        .manage(blog)
+        .manage(gallery)
+        .manage(resizer::ResizedImages::default())
+        .mount(
+            "/blog",
+            routes![
+                blog::get_index,
+                blog::get_index_page,
+                blog::get_rss_feed,
+                blog::get_atom_feed,
+                blog::get_tags,
+                blog::get_tag,
+                blog::get_author,
+                blog::get_search,
+                blog::get_archive,
+                blog::get_year,
+                blog::get_month,
+                blog::get_post
+            ],
+        )
         .mount(
             "/code_art",
             routes![code_art::get_index, code_art::get_resizer],
         )
+        .mount("/resized", routes![resizer::get_resized])
         .mount(
             "/files",
             rocket_contrib::serve::StaticFiles::new(
@@ -76,8 +106,85 @@ fn main() {
             routes![
                 static_pages::get_index,
                 static_pages::get_about,
-                robots::get_robots_txt
+                robots::get_robots_txt,
+                sitemap::get_sitemap
             ],
         )
-        .launch();
+}
+
+fn main() {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info,spuri_io=debug");
+    }
+    env_logger::init();
+
+    let base_arc = Arc::new(BASE.clone());
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("export") => match args.get(2) {
+            Some(outdir) => {
+                info!("Exporting...");
+                let blog = blog::Blog::load(base_arc.clone());
+                let gallery = code_art::Gallery::load(base_arc);
+                if let Err(err) = export::export(rocket(blog, gallery), Path::new(outdir)) {
+                    error!("Could not export the site: {}", err);
+                    process::exit(1);
+                }
+            }
+            None => {
+                eprintln!("Usage: {} export <outdir>", args[0]);
+                process::exit(2);
+            }
+        },
+        _ => {
+            info!("Starting...");
+            let blog = blog::Blog::new(base_arc.clone());
+            let gallery = code_art::Gallery::new(base_arc);
+            rocket(blog, gallery).launch();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rocket::http::Status;
+    use rocket::local::Client;
+
+    #[test]
+    fn routes_do_not_collide() {
+        let base_arc = Arc::new(BASE.clone());
+        let blog = blog::Blog::load(base_arc.clone());
+        let gallery = code_art::Gallery::load(base_arc);
+        assert!(Client::new(rocket(blog, gallery)).is_ok());
+    }
+
+    #[test]
+    fn old_code_art_resizer_links_redirect() {
+        let base_arc = Arc::new(BASE.clone());
+        let blog = blog::Blog::load(base_arc.clone());
+        let gallery = code_art::Gallery::load(base_arc);
+        let client = Client::new(rocket(blog, gallery)).unwrap();
+        let response = client
+            .get("/code_art/resizer?width=640&height=360&src=/files/code_art/Aquarium.png")
+            .dispatch();
+        assert_eq!(response.status(), Status::PermanentRedirect);
+        assert_eq!(
+            response.headers().get_one("Location"),
+            Some("/resized/640/code_art/Aquarium.png")
+        );
+    }
+
+    #[test]
+    fn old_blog_page_queries_redirect() {
+        let base_arc = Arc::new(BASE.clone());
+        let blog = blog::Blog::load(base_arc.clone());
+        let gallery = code_art::Gallery::load(base_arc);
+        let client = Client::new(rocket(blog, gallery)).unwrap();
+        let response = client.get("/blog?page=3").dispatch();
+        assert_eq!(response.status(), Status::PermanentRedirect);
+        assert_eq!(response.headers().get_one("Location"), Some("/blog/page/3"));
+        let response = client.get("/blog?page=1").dispatch();
+        assert_eq!(response.headers().get_one("Location"), Some("/blog"));
+    }
 }
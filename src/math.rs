@@ -0,0 +1,517 @@
+//! Server-side rendering of TeX math in blog posts to MathML, so that readers don't need any JavaScript to see it. Only a subset of TeX
+//! is understood: scripts, fractions, roots, groups, fonts like `\mathbb`, and the common greek letters, operators and relations.
+//! Anything else is rendered as an error in place rather than failing the whole post.
+//!
+//! Math has to be pulled out of the markdown before it is parsed, otherwise pulldown-cmark would treat `\{`, `*` and friends as markdown.
+//! Each `$...$` (inline) or `$$...$$` (display) span is swapped for a placeholder that markdown leaves alone, and the rendered MathML is
+//! put back in after the markdown is converted to HTML.
+
+use base::push_escaped;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/// Every MathML element that can be rendered, for the sanitizer whitelist
+pub const TAGS: &[&str] = &[
+    "math", "mrow", "mi", "mn", "mo", "mtext", "mspace", "merror", "msup", "msub", "msubsup",
+    "mfrac", "msqrt", "mroot",
+];
+
+fn placeholder(index: usize) -> String {
+    // Private use characters can't come up in a post by accident and markdown passes them through untouched
+    format!("\u{E000}{}\u{E001}", index)
+}
+
+/// A span of math taken out of a post
+#[derive(PartialEq, Debug)]
+pub struct Math {
+    /// As written, without the dollar signs
+    pub tex: String,
+    pub mathml: String,
+}
+
+/// Swaps every math span outside of code for a placeholder, returning the new markdown and the math for each placeholder in order
+pub fn extract(markdown: &str) -> (String, Vec<Math>) {
+    let mut extracted = String::with_capacity(markdown.len());
+    let mut rendered = Vec::new();
+    let mut prose = String::new();
+    let mut fence: Option<&str> = None;
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) => {
+                if trimmed.starts_with(marker) {
+                    fence = None;
+                }
+            }
+            None if trimmed.starts_with("```") || trimmed.starts_with("~~~") => {
+                extract_from_prose(&prose, &mut extracted, &mut rendered);
+                prose.clear();
+                fence = Some(&trimmed[..3]);
+            }
+            None => {
+                prose.push_str(line);
+                prose.push('\n');
+                continue;
+            }
+        }
+        extracted.push_str(line);
+        extracted.push('\n');
+    }
+    extract_from_prose(&prose, &mut extracted, &mut rendered);
+    (extracted, rendered)
+}
+
+/// Puts the rendered MathML back in place of the placeholders
+pub fn restore(html: &str, rendered: &[Math]) -> String {
+    rendered
+        .iter()
+        .enumerate()
+        .fold(html.to_string(), |html, (index, math)| {
+            html.replace(&placeholder(index), &math.mathml)
+        })
+}
+
+/// Puts the TeX back in place of the placeholders, for where markup can't go (i.e. heading anchors)
+pub fn restore_tex(text: &str, rendered: &[Math]) -> String {
+    rendered
+        .iter()
+        .enumerate()
+        .fold(text.to_string(), |text, (index, math)| {
+            text.replace(&placeholder(index), &math.tex)
+        })
+}
+
+/// Drops the placeholders from text, for when only the prose matters
+pub fn strip_placeholders(text: &str) -> String {
+    let mut in_placeholder = false;
+    text.chars()
+        .filter(|&c| match c {
+            '\u{E000}' => {
+                in_placeholder = true;
+                false
+            }
+            '\u{E001}' => {
+                in_placeholder = false;
+                false
+            }
+            _ => !in_placeholder,
+        })
+        .collect()
+}
+
+fn extract_from_prose(prose: &str, extracted: &mut String, rendered: &mut Vec<Math>) {
+    let chars: Vec<char> = prose.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if chars.get(i + 1) == Some(&'$') => {
+                extracted.push_str("\\$");
+                i += 2;
+            }
+            // Code spans are copied through verbatim
+            '`' => {
+                let run = chars[i..].iter().take_while(|&&c| c == '`').count();
+                let close = (i + run..chars.len()).find(|&j| {
+                    chars[j..].iter().take_while(|&&c| c == '`').count() == run
+                        && (j == 0 || chars[j - 1] != '`')
+                });
+                let end = close.map_or(i + run, |close| close + run);
+                extracted.extend(&chars[i..end]);
+                i = end;
+            }
+            '$' if chars.get(i + 1) == Some(&'$') => {
+                match (i + 2..chars.len())
+                    .find(|&j| chars[j] == '$' && chars.get(j + 1) == Some(&'$'))
+                {
+                    Some(close) => {
+                        let tex: String = chars[i + 2..close].iter().collect();
+                        extracted.push_str(&placeholder(rendered.len()));
+                        rendered.push(Math {
+                            mathml: render(&tex, true),
+                            tex,
+                        });
+                        i = close + 2;
+                    }
+                    None => {
+                        extracted.push_str("$$");
+                        i += 2;
+                    }
+                }
+            }
+            // Like pandoc, inline math can't start or end with whitespace or be followed by a digit, so that prices like $5 and $10
+            // aren't mistaken for math. It also can't span lines.
+            '$' if chars.get(i + 1).map_or(false, |c| !c.is_whitespace()) => {
+                let close = (i + 1..chars.len())
+                    .take_while(|&j| chars[j] != '\n')
+                    .find(|&j| {
+                        chars[j] == '$'
+                            && !chars[j - 1].is_whitespace()
+                            && chars[j - 1] != '\\'
+                            && !chars.get(j + 1).map_or(false, |c| c.is_ascii_digit())
+                    });
+                match close {
+                    Some(close) => {
+                        let tex: String = chars[i + 1..close].iter().collect();
+                        extracted.push_str(&placeholder(rendered.len()));
+                        rendered.push(Math {
+                            mathml: render(&tex, false),
+                            tex,
+                        });
+                        i = close + 1;
+                    }
+                    None => {
+                        extracted.push('$');
+                        i += 1;
+                    }
+                }
+            }
+            c => {
+                extracted.push(c);
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Renders a single TeX math expression to a MathML `math` element
+pub fn render(tex: &str, display: bool) -> String {
+    let mut parser = Parser {
+        chars: tex.chars().peekable(),
+    };
+    format!(
+        "<math{}><mrow>{}</mrow></math>",
+        if display { " display=\"block\"" } else { "" },
+        parser.parse_row(None)
+    )
+}
+
+fn element(tag: &str, content: &str) -> String {
+    let mut element = format!("<{}>", tag);
+    push_escaped(&mut element, content);
+    element.push_str(&format!("</{}>", tag));
+    element
+}
+
+fn mi(mathvariant: &str, content: &str) -> String {
+    let mut mi = format!("<mi mathvariant=\"{}\">", mathvariant);
+    push_escaped(&mut mi, content);
+    mi.push_str("</mi>");
+    mi
+}
+
+/// Symbols by command name, and whether they are identifiers (`mi`) rather than operators (`mo`)
+const SYMBOLS: &[(&str, &str, bool)] = &[
+    ("alpha", "α", true),
+    ("beta", "β", true),
+    ("gamma", "γ", true),
+    ("delta", "δ", true),
+    ("epsilon", "ϵ", true),
+    ("varepsilon", "ε", true),
+    ("zeta", "ζ", true),
+    ("eta", "η", true),
+    ("theta", "θ", true),
+    ("iota", "ι", true),
+    ("kappa", "κ", true),
+    ("lambda", "λ", true),
+    ("mu", "μ", true),
+    ("nu", "ν", true),
+    ("xi", "ξ", true),
+    ("pi", "π", true),
+    ("rho", "ρ", true),
+    ("sigma", "σ", true),
+    ("tau", "τ", true),
+    ("phi", "ϕ", true),
+    ("varphi", "φ", true),
+    ("chi", "χ", true),
+    ("psi", "ψ", true),
+    ("omega", "ω", true),
+    ("Gamma", "Γ", true),
+    ("Delta", "Δ", true),
+    ("Theta", "Θ", true),
+    ("Lambda", "Λ", true),
+    ("Pi", "Π", true),
+    ("Sigma", "Σ", true),
+    ("Phi", "Φ", true),
+    ("Psi", "Ψ", true),
+    ("Omega", "Ω", true),
+    ("infty", "∞", true),
+    ("partial", "∂", true),
+    ("nabla", "∇", true),
+    ("emptyset", "∅", true),
+    ("ell", "ℓ", true),
+    ("sum", "∑", false),
+    ("prod", "∏", false),
+    ("int", "∫", false),
+    ("le", "≤", false),
+    ("leq", "≤", false),
+    ("ge", "≥", false),
+    ("geq", "≥", false),
+    ("ne", "≠", false),
+    ("neq", "≠", false),
+    ("approx", "≈", false),
+    ("equiv", "≡", false),
+    ("sim", "∼", false),
+    ("in", "∈", false),
+    ("notin", "∉", false),
+    ("subset", "⊂", false),
+    ("subseteq", "⊆", false),
+    ("cup", "∪", false),
+    ("cap", "∩", false),
+    ("forall", "∀", false),
+    ("exists", "∃", false),
+    ("pm", "±", false),
+    ("times", "×", false),
+    ("cdot", "⋅", false),
+    ("div", "÷", false),
+    ("to", "→", false),
+    ("rightarrow", "→", false),
+    ("leftarrow", "←", false),
+    ("Rightarrow", "⇒", false),
+    ("iff", "⟺", false),
+    ("mid", "∣", false),
+    ("ldots", "…", false),
+    ("cdots", "⋯", false),
+    ("lfloor", "⌊", false),
+    ("rfloor", "⌋", false),
+];
+
+/// Multi-letter function names that are set upright
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "log", "ln", "exp", "lim", "max", "min", "sup", "inf", "det", "gcd",
+];
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses until `terminator` (which is consumed) or the end of input
+    fn parse_row(&mut self, terminator: Option<char>) -> String {
+        let mut nodes: Vec<String> = Vec::new();
+        while let Some(&c) = self.chars.peek() {
+            if Some(c) == terminator {
+                self.chars.next();
+                break;
+            }
+            match c {
+                '^' | '_' => {
+                    self.chars.next();
+                    let base = nodes.pop().unwrap_or_else(|| "<mrow></mrow>".to_string());
+                    let script = self.parse_atom();
+                    let other = if c == '^' { '_' } else { '^' };
+                    let node = if self.chars.peek() == Some(&other) {
+                        self.chars.next();
+                        let other_script = self.parse_atom();
+                        let (sub, sup) = if c == '_' {
+                            (script, other_script)
+                        } else {
+                            (other_script, script)
+                        };
+                        format!("<msubsup>{}{}{}</msubsup>", base, sub, sup)
+                    } else if c == '^' {
+                        format!("<msup>{}{}</msup>", base, script)
+                    } else {
+                        format!("<msub>{}{}</msub>", base, script)
+                    };
+                    nodes.push(node);
+                }
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                }
+                _ => {
+                    let atom = self.parse_atom();
+                    nodes.push(atom);
+                }
+            }
+        }
+        nodes.concat()
+    }
+
+    fn parse_atom(&mut self) -> String {
+        while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+            self.chars.next();
+        }
+        match self.chars.next() {
+            None => "<mrow></mrow>".to_string(),
+            Some('{') => format!("<mrow>{}</mrow>", self.parse_row(Some('}'))),
+            Some('\\') => self.parse_command(),
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_ascii_digit() || c == '.' {
+                        number.push(c);
+                        self.chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                element("mn", &number)
+            }
+            Some(c) if c.is_alphabetic() => element("mi", &c.to_string()),
+            Some(c) => element("mo", &c.to_string()),
+        }
+    }
+
+    /// The raw text of a `{...}` group, as taken by `\text` and the font commands
+    fn parse_text_group(&mut self) -> String {
+        while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+            self.chars.next();
+        }
+        if self.chars.peek() != Some(&'{') {
+            return self.chars.next().map(|c| c.to_string()).unwrap_or_default();
+        }
+        self.chars.next();
+        let mut depth = 0;
+        let mut text = String::new();
+        for c in &mut self.chars {
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => break,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            text.push(c);
+        }
+        text
+    }
+
+    fn parse_command(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_alphabetic() {
+                name.push(c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+        if name.is_empty() {
+            return match self.chars.next() {
+                Some(',') | Some(':') | Some(';') | Some(' ') => {
+                    "<mspace width=\"0.2em\"></mspace>".to_string()
+                }
+                Some('!') | None => String::new(),
+                Some(c) => element("mo", &c.to_string()),
+            };
+        }
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_atom();
+                let denominator = self.parse_atom();
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "sqrt" => {
+                while self.chars.peek().map_or(false, |c| c.is_whitespace()) {
+                    self.chars.next();
+                }
+                if self.chars.peek() == Some(&'[') {
+                    self.chars.next();
+                    let index = self.parse_row(Some(']'));
+                    let radicand = self.parse_atom();
+                    format!("<mroot>{}<mrow>{}</mrow></mroot>", radicand, index)
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.parse_atom())
+                }
+            }
+            "text" | "textrm" | "mbox" => element("mtext", &self.parse_text_group()),
+            "mathrm" | "operatorname" => mi("normal", &self.parse_text_group()),
+            "mathbf" => mi("bold", &self.parse_text_group()),
+            "mathcal" => mi("script", &self.parse_text_group()),
+            "mathbb" => {
+                let text = self.parse_text_group();
+                let double_struck: String = text
+                    .chars()
+                    .map(|c| match c {
+                        'C' => 'ℂ',
+                        'N' => 'ℕ',
+                        'Q' => 'ℚ',
+                        'R' => 'ℝ',
+                        'Z' => 'ℤ',
+                        c => c,
+                    })
+                    .collect();
+                mi("double-struck", &double_struck)
+            }
+            // Delimiters are left at their natural size
+            "left" | "right" => {
+                if self.chars.peek() == Some(&'.') {
+                    self.chars.next();
+                    String::new()
+                } else {
+                    self.parse_atom()
+                }
+            }
+            "quad" => "<mspace width=\"1em\"></mspace>".to_string(),
+            "qquad" => "<mspace width=\"2em\"></mspace>".to_string(),
+            name if FUNCTIONS.contains(&name) => element("mi", name),
+            name => match SYMBOLS.iter().find(|symbol| symbol.0 == name) {
+                Some(&(_, symbol, true)) => element("mi", symbol),
+                Some(&(_, symbol, false)) => element("mo", symbol),
+                None => format!(
+                    "<merror>{}</merror>",
+                    element("mtext", &format!("\\{}", name))
+                ),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scripts_and_fractions_render() {
+        assert_eq!(
+            render("x_i^2 + \\frac{1}{n}", false),
+            "<math><mrow><msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup><mo>+</mo>\
+             <mfrac><mrow><mn>1</mn></mrow><mrow><mi>n</mi></mrow></mfrac></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn symbols_and_fonts_render() {
+        assert_eq!(
+            render("p \\in \\mathbb{Z}^n", true),
+            "<math display=\"block\"><mrow><mi>p</mi><mo>∈</mo><msup>\
+             <mi mathvariant=\"double-struck\">ℤ</mi><mi>n</mi></msup></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn unknown_commands_are_errors() {
+        assert_eq!(
+            render("\\foo", false),
+            "<math><mrow><merror><mtext>\\foo</mtext></merror></mrow></math>"
+        );
+    }
+
+    #[test]
+    fn math_is_extracted_outside_of_code() {
+        let (markdown, rendered) =
+            extract("Let $x<y$, `$not$` and\n$$a$$\n```\n$code$\n```\ncost $5 or $10\n");
+        assert_eq!(
+            markdown,
+            "Let \u{E000}0\u{E001}, `$not$` and\n\u{E000}1\u{E001}\n```\n$code$\n```\ncost $5 or $10\n"
+        );
+        assert_eq!(
+            rendered,
+            vec![
+                Math {
+                    tex: "x<y".to_string(),
+                    mathml: "<math><mrow><mi>x</mi><mo>&lt;</mo><mi>y</mi></mrow></math>"
+                        .to_string(),
+                },
+                Math {
+                    tex: "a".to_string(),
+                    mathml: "<math display=\"block\"><mrow><mi>a</mi></mrow></math>".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            restore("<p>\u{E000}1\u{E001}</p>", &rendered),
+            "<p><math display=\"block\"><mrow><mi>a</mi></mrow></math></p>"
+        );
+        assert_eq!(restore_tex("Let \u{E000}0\u{E001}", &rendered), "Let x<y");
+    }
+}
@@ -0,0 +1,145 @@
+//! What a page looks like when a link to it is shared. Open Graph covers most sites, Twitter Cards cover Twitter, and schema.org JSON-LD
+//! covers search engines. Pages render a PageMeta into the meta block of base.html, and anything without one keeps the site-wide
+//! description.
+
+use askama::Template;
+use authors::Author;
+use base::*;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+/// For pages that don't have anything more specific to say, same as the default in base.html
+pub const SITE_DESCRIPTION: &str =
+    "Sameer Puri's personal island in the seas of the interwebs. Come visit!";
+
+/// Previews cut descriptions off at around this many characters
+const DESCRIPTION_MAX_LENGTH: usize = 160;
+
+#[derive(Template)]
+#[template(path = "page_meta.html")]
+pub struct PageMeta {
+    pub public_url: &'static str,
+    pub title: String,
+    /// Plain text
+    pub description: String,
+    /// Relative to the site root
+    pub path: String,
+    /// Open Graph type, i.e. "website" or "article"
+    pub kind: &'static str,
+    pub image: Option<MetaImage>,
+    /// The rest are only for articles
+    pub published: Option<DateTime<Utc>>,
+    pub modified: Option<DateTime<Utc>>,
+    pub authors: Vec<Author>,
+    pub tags: Vec<String>,
+    /// JSON-LD, already escaped for a script element
+    pub structured_data: String,
+}
+
+impl PageMeta {
+    /// Any page that isn't an article, described to search engines as a `schema_type` (i.e. "CollectionPage")
+    pub fn website(schema_type: &str, title: &str, description: &str, path: &str) -> PageMeta {
+        PageMeta {
+            public_url: &PUBLIC_URL,
+            title: title.to_string(),
+            description: description.to_string(),
+            path: path.to_string(),
+            kind: "website",
+            image: None,
+            published: None,
+            modified: None,
+            authors: Vec::new(),
+            tags: Vec::new(),
+            structured_data: to_script_json(&json!({
+                "@context": "https://schema.org",
+                "@type": schema_type,
+                "name": title,
+                "description": description,
+                "url": format!("{}{}", *PUBLIC_URL, path),
+            })),
+        }
+    }
+}
+
+/// The picture shown alongside a shared link
+#[derive(Hash, Eq, PartialEq, Debug, Clone)]
+pub struct MetaImage {
+    /// As linked to from the page, so usually relative to the site root
+    pub src: String,
+    pub alt: String,
+    /// Only known for local images
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+impl MetaImage {
+    pub fn url(&self) -> String {
+        absolute_url(&PUBLIC_URL, &self.src)
+    }
+
+    /// A schema.org ImageObject
+    pub fn to_image_object(&self) -> Value {
+        let mut image_object = json!({
+            "@type": "ImageObject",
+            "url": self.url(),
+            "contentUrl": self.url(),
+            "caption": self.alt,
+        });
+        if let (Some(width), Some(height)) = (self.width, self.height) {
+            image_object["width"] = json!(width);
+            image_object["height"] = json!(height);
+        }
+        image_object
+    }
+}
+
+pub fn absolute_url(public_url: &str, url: &str) -> String {
+    if url.starts_with('/') {
+        format!("{}{}", public_url, url)
+    } else {
+        url.to_string()
+    }
+}
+
+/// Serializes structured data for a `<script type="application/ld+json">` element. JSON doesn't need `/` escaped but HTML does, or a
+/// string containing `</script>` would end the element early.
+pub fn to_script_json(value: &Value) -> String {
+    value.to_string().replace("</", "<\\/")
+}
+
+/// Shortens plain text into a description, cutting it off between words
+pub fn text_to_description(text: &str) -> String {
+    let mut description = String::new();
+    for word in text.split_whitespace() {
+        if description.chars().count() + word.chars().count() + 1 > DESCRIPTION_MAX_LENGTH {
+            description.push('…');
+            break;
+        }
+        if !description.is_empty() {
+            description.push(' ');
+        }
+        description.push_str(word);
+    }
+    description
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn descriptions_end_between_words() {
+        assert_eq!(text_to_description(" Pen\n plotter "), "Pen plotter");
+        let description = text_to_description(&"word ".repeat(100));
+        assert!(description.ends_with("word…"));
+        assert!(description.chars().count() <= DESCRIPTION_MAX_LENGTH + 1);
+    }
+
+    #[test]
+    fn script_json_cannot_end_the_script() {
+        assert_eq!(
+            to_script_json(&json!({ "name": "</script>" })),
+            "{\"name\":\"<\\/script>\"}"
+        );
+    }
+}
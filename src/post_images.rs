@@ -0,0 +1,49 @@
+//! Responsive images for blog posts. Images under /files are rewritten into `srcset` markup pointing at the resizer, see resizer.rs.
+
+use base::push_escaped;
+use resizer;
+
+/// Images take up at most the width of the container, which style.css caps at 1270px
+const SIZES: &str = "(max-width: 1310px) 100vw, 1270px";
+
+pub struct ResponsiveImage {
+    /// The `img` element
+    pub html: String,
+    /// Every resized version the `srcset` can ask for
+    pub variant_urls: Vec<String>,
+    /// Of the original
+    pub width: u32,
+    pub height: u32,
+}
+
+/// None if the image isn't a local raster image whose size can be read
+pub fn img(src: &str, alt: &str, title: &str) -> Option<ResponsiveImage> {
+    if !resizer::can_resize(src) {
+        return None;
+    }
+    let (width, height) =
+        resizer::src_to_path(src).and_then(|path| image::image_dimensions(path).ok())?;
+    let mut html = String::from("<img src=\"");
+    push_escaped(&mut html, src);
+    html.push_str("\" srcset=\"");
+    push_escaped(&mut html, &resizer::srcset(src, width));
+    html.push_str(&format!(
+        "\" sizes=\"{}\" width=\"{}\" height=\"{}\" loading=\"lazy\" alt=\"",
+        SIZES, width, height
+    ));
+    push_escaped(&mut html, alt);
+    if !title.is_empty() {
+        html.push_str("\" title=\"");
+        push_escaped(&mut html, title);
+    }
+    html.push_str("\">");
+    Some(ResponsiveImage {
+        html,
+        variant_urls: resizer::variant_urls(src, width)
+            .into_iter()
+            .map(|(_, url)| url)
+            .collect(),
+        width,
+        height,
+    })
+}
@@ -0,0 +1,227 @@
+//! Scaled down copies of the images under /files, shared by the code art gallery and blog posts for their `srcset`s. Each copy is made
+//! the first time it is asked for and kept in memory until the original changes.
+
+use base::ASSETS_URL_PREFIX;
+use image::{FilterType, GenericImageView, ImageOutputFormat};
+use rocket::State;
+use rocket::{http::ContentType, http::Status, response::Result as RocketResult, Response};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use url::percent_encoding::percent_decode;
+
+// Of note (to future me) is the fact that 4K UHD/WQHD are not included; my images are never larger than FHD (yet), so there is no reason
+// to provide these widths. Only a few are offered at all so that nobody can fill up the cache with every width imaginable.
+const AVAILABLE_WIDTHS: [u32; 4] = [1920, 1280, 960, 640];
+
+const FOLDER_PATH: &str = "./files";
+
+const JPEG_QUALITY: u8 = 85;
+
+/// Big enough for large link previews without being the full size
+const PREVIEW_WIDTH: u32 = 1280;
+
+pub struct Resized {
+    /// Of the original when it was resized
+    modified: SystemTime,
+    content_type: ContentType,
+    bytes: Vec<u8>,
+}
+
+/// Resized images by the canonical path of the original and width, so an image linked to in different ways is only resized once. Only
+/// images under /files at one of AVAILABLE_WIDTHS are ever added.
+pub type ResizedImages = Arc<RwLock<HashMap<(PathBuf, u32), Resized>>>;
+
+/// `path` is relative to /files
+#[get("/<width>/<path..>")]
+pub fn get_resized(
+    resized_images: State<ResizedImages>,
+    width: u32,
+    path: PathBuf,
+) -> RocketResult {
+    // This is the ideal response code here. The request is valid and well formed but it will not be processed because it doesn't match
+    // the expectation that it should be one of AVAILABLE_WIDTHS. If this happens, in all likelihood, someone is just messing around.
+    if !AVAILABLE_WIDTHS.contains(&width) {
+        return Err(Status::UnprocessableEntity);
+    }
+    let format = path_to_format(&path).ok_or(Status::NotFound)?;
+    let path = canonicalize(&path).ok_or(Status::NotFound)?;
+    let modified = fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .map_err(|_| Status::NotFound)?;
+    let key = (path, width);
+    if let Some(resized) = resized_images.read().unwrap().get(&key) {
+        if resized.modified == modified {
+            return Ok(image_response(
+                resized.content_type.clone(),
+                resized.bytes.clone(),
+            ));
+        }
+    }
+    let dynamic_image = image::open(&key.0).map_err(|_| Status::NotFound)?;
+    if width >= dynamic_image.width() {
+        // Larger widths are never in a srcset, the original is
+        return Err(Status::UnprocessableEntity);
+    }
+    let resized_image = dynamic_image.resize(width, u32::max_value(), FilterType::Triangle);
+    let mut bytes = Vec::new();
+    let content_type = match format {
+        ImageOutputFormat::JPEG(_) => ContentType::JPEG,
+        _ => ContentType::PNG,
+    };
+    resized_image
+        .write_to(&mut bytes, format)
+        .map_err(|_| Status::InternalServerError)?;
+    debug!("Resized {:?} to {}px wide", key.0, width);
+    resized_images.write().unwrap().insert(
+        key,
+        Resized {
+            modified,
+            content_type: content_type.clone(),
+            bytes: bytes.clone(),
+        },
+    );
+    Ok(image_response(content_type, bytes))
+}
+
+fn image_response(content_type: ContentType, bytes: Vec<u8>) -> Response<'static> {
+    Response::build()
+        .header(content_type)
+        .sized_body(Cursor::new(bytes))
+        .finalize()
+}
+
+/// Resolves symlinks and refuses anything that ends up outside of /files
+fn canonicalize(relative_path: &Path) -> Option<PathBuf> {
+    let folder = Path::new(FOLDER_PATH).canonicalize().ok()?;
+    let path = folder.join(relative_path).canonicalize().ok()?;
+    if path.starts_with(&folder) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Where an image under /files lives on disk, relative to the working directory. Anything trying to climb out of /files is refused.
+pub fn src_to_path(src: &str) -> Option<PathBuf> {
+    if !src.starts_with(ASSETS_URL_PREFIX) {
+        return None;
+    }
+    let relative_path = PathBuf::from(
+        percent_decode(src[1..].as_bytes())
+            .decode_utf8_lossy()
+            .as_ref(),
+    );
+    if relative_path.components().all(|component| match component {
+        Component::Normal(_) => true,
+        _ => false,
+    }) {
+        Some(relative_path)
+    } else {
+        None
+    }
+}
+
+/// Vector images and anything animated are left alone
+fn path_to_format(path: &Path) -> Option<ImageOutputFormat> {
+    match path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+    {
+        Some(ref extension) if extension == "jpg" || extension == "jpeg" => {
+            Some(ImageOutputFormat::JPEG(JPEG_QUALITY))
+        }
+        Some(ref extension) if extension == "png" => Some(ImageOutputFormat::PNG),
+        _ => None,
+    }
+}
+
+/// Whether `src` is a local raster image
+pub fn can_resize(src: &str) -> bool {
+    src.starts_with(ASSETS_URL_PREFIX) && path_to_format(Path::new(src)).is_some()
+}
+
+/// Resizer URLs for the widths smaller than the original, smallest first
+pub fn variant_urls(src: &str, width: u32) -> Vec<(u32, String)> {
+    if !can_resize(src) {
+        return Vec::new();
+    }
+    AVAILABLE_WIDTHS
+        .iter()
+        .rev()
+        .filter(|&&available_width| available_width < width)
+        .map(|&available_width| {
+            (
+                available_width,
+                format!(
+                    "/resized/{}/{}",
+                    available_width,
+                    &src[ASSETS_URL_PREFIX.len()..]
+                ),
+            )
+        })
+        .collect()
+}
+
+/// The largest variant no wider than PREVIEW_WIDTH and its height, for link previews. robots.txt keeps crawlers (i.e. Twitterbot) out of
+/// /files, so previews link here instead. None if the image is too small to have any variants.
+pub fn preview(src: &str, width: u32, height: u32) -> Option<(String, u32, u32)> {
+    variant_urls(src, width)
+        .into_iter()
+        .filter(|&(variant_width, _)| variant_width <= PREVIEW_WIDTH)
+        .last()
+        .map(|(variant_width, url)| (url, variant_width, height * variant_width / width))
+}
+
+/// The variants along with the original
+pub fn srcset(src: &str, width: u32) -> String {
+    variant_urls(src, width)
+        .into_iter()
+        .chain(std::iter::once((width, src.to_string())))
+        .map(|(width, url)| format!("{} {}w", url, width))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn srcset_only_scales_down() {
+        assert_eq!(
+            srcset("/files/plotter.jpg", 1000),
+            "/resized/640/plotter.jpg 640w, /resized/960/plotter.jpg 960w, /files/plotter.jpg 1000w"
+        );
+        assert_eq!(srcset("/files/plant.svg", 1000), "/files/plant.svg 1000w");
+    }
+
+    #[test]
+    fn previews_are_the_largest_variant_that_fits() {
+        assert_eq!(
+            preview("/files/plotter.jpg", 1920, 1080),
+            Some(("/resized/1280/plotter.jpg".to_string(), 1280, 720))
+        );
+        assert_eq!(
+            preview("/files/plotter.jpg", 1000, 500),
+            Some(("/resized/960/plotter.jpg".to_string(), 960, 480))
+        );
+        assert_eq!(preview("/files/plotter.jpg", 600, 300), None);
+    }
+
+    #[test]
+    fn paths_stay_in_files() {
+        assert_eq!(
+            src_to_path("/files/plotter%20demo.jpg"),
+            Some(PathBuf::from("files/plotter demo.jpg"))
+        );
+        assert_eq!(src_to_path("/files/../Cargo.toml"), None);
+        assert_eq!(src_to_path("/blog/plotter.jpg"), None);
+        assert_eq!(canonicalize(Path::new("../Cargo.toml")), None);
+        assert!(canonicalize(Path::new("style.css")).is_some());
+    }
+}
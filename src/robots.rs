@@ -1,7 +1,10 @@
+use base::*;
+
 const ROBOTS_TXT: &str = "User-agent: *
 Disallow: /files";
 
+/// The sitemap has to be given as an absolute URL, so it depends on where the site is hosted
 #[get("/robots.txt")]
-pub fn get_robots_txt() -> &'static str {
-    ROBOTS_TXT
+pub fn get_robots_txt() -> String {
+    format!("{}\n\nSitemap: {}/sitemap.xml\n", ROBOTS_TXT, *PUBLIC_URL)
 }
@@ -0,0 +1,251 @@
+//! Which HTML posts may contain. Rendered markdown is cleaned with ammonia, keeping whatever the renderer itself produces (math,
+//! highlighted code, heading anchors) and whatever the policy file lets authors write by hand, so that a new element only needs a line
+//! of YAML.
+
+use ammonia::Builder;
+use highlight;
+use html5ever::rcdom::{Handle, NodeData};
+use math;
+use std::collections::HashMap;
+use std::error;
+use std::fs;
+use std::path::Path;
+use std::sync::RwLock;
+use url::Url;
+
+lazy_static! {
+    /// Loaded along with the posts, and reloaded whenever the file changes
+    static ref POLICY: RwLock<Policy> = RwLock::new(Policy::default());
+}
+
+static HEADING_TAGS: [&str; 6] = ["h1", "h2", "h3", "h4", "h5", "h6"];
+
+#[derive(Deserialize, Debug)]
+pub struct Policy {
+    /// Allowed on top of ammonia's defaults
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Attributes allowed with any value, by tag
+    #[serde(default)]
+    attributes: HashMap<String, Vec<String>>,
+    /// Values the class attribute may have, by tag
+    #[serde(default)]
+    classes: HashMap<String, Vec<String>>,
+    /// Values other attributes may have, by tag and then attribute
+    #[serde(default)]
+    attribute_values: HashMap<String, HashMap<String, Vec<String>>>,
+    /// Schemes links and sources may use. Left out, ammonia's defaults apply.
+    #[serde(default)]
+    url_schemes: Vec<String>,
+    /// Hosts iframes may embed. Iframes pointing anywhere else are dropped, so allowing the tag alone lets nothing through.
+    #[serde(default)]
+    iframe_hosts: Vec<String>,
+}
+
+fn to_strings(strs: &[&str]) -> Vec<String> {
+    strs.iter().map(|s| s.to_string()).collect()
+}
+
+impl Default for Policy {
+    /// What posts could always contain (footnotes, table alignment, task lists and videos), so that they still render the same without
+    /// the policy file
+    fn default() -> Policy {
+        let mut input_values = HashMap::new();
+        input_values.insert("type".to_string(), to_strings(&["checkbox"]));
+        let mut attribute_values = HashMap::new();
+        attribute_values.insert("input".to_string(), input_values);
+        Policy {
+            tags: to_strings(&["video", "input"]),
+            attributes: [
+                ("video", vec!["controls", "src"]),
+                ("div", vec!["id"]),
+                ("th", vec!["align"]),
+                ("td", vec!["align"]),
+                ("input", vec!["checked", "disabled"]),
+            ]
+            .iter()
+            .map(|(tag, attributes)| (tag.to_string(), to_strings(attributes)))
+            .collect(),
+            classes: [
+                ("div", "footnote-definition"),
+                ("sup", "footnote-definition-label"),
+            ]
+            .iter()
+            .map(|(tag, class)| (tag.to_string(), vec![class.to_string()]))
+            .collect(),
+            attribute_values,
+            url_schemes: Vec::new(),
+            iframe_hosts: Vec::new(),
+        }
+    }
+}
+
+/// Replaces the policy with the default one plus whatever the file allows. If it can't be loaded, the previous one stays.
+pub fn load(path: &Path) -> Result<(), Box<error::Error>> {
+    let mut policy = Policy::default();
+    policy.extend(serde_yaml::from_str(&fs::read_to_string(path)?)?);
+    *POLICY.write().unwrap() = policy;
+    Ok(())
+}
+
+pub fn clean(unsafe_html_text: &str) -> String {
+    POLICY.read().unwrap().clean(unsafe_html_text)
+}
+
+impl Policy {
+    fn extend(&mut self, policy: Policy) {
+        self.tags.extend(policy.tags);
+        policy.attributes.into_iter().for_each(|(tag, attributes)| {
+            self.attributes
+                .entry(tag)
+                .or_insert_with(Vec::new)
+                .extend(attributes)
+        });
+        policy.classes.into_iter().for_each(|(tag, classes)| {
+            self.classes
+                .entry(tag)
+                .or_insert_with(Vec::new)
+                .extend(classes)
+        });
+        policy
+            .attribute_values
+            .into_iter()
+            .for_each(|(tag, attribute_values)| {
+                let tag_attribute_values = self
+                    .attribute_values
+                    .entry(tag)
+                    .or_insert_with(HashMap::new);
+                attribute_values
+                    .into_iter()
+                    .for_each(|(attribute, values)| {
+                        tag_attribute_values
+                            .entry(attribute)
+                            .or_insert_with(Vec::new)
+                            .extend(values)
+                    });
+            });
+        self.url_schemes.extend(policy.url_schemes);
+        self.iframe_hosts.extend(policy.iframe_hosts);
+    }
+
+    fn clean(&self, unsafe_html_text: &str) -> String {
+        let code_classes = highlight::code_classes();
+        let mut builder = Builder::default();
+        builder
+            .add_tags(math::TAGS)
+            .add_tag_attributes("math", &["display"])
+            .add_tag_attributes("mi", &["mathvariant"])
+            .add_tag_attributes("mspace", &["width"])
+            .add_tag_attributes("img", &["srcset", "sizes", "loading"])
+            .add_tag_attribute_values("code", "class", &code_classes)
+            .add_tag_attribute_values("span", "class", &highlight::SPAN_CLASSES)
+            .add_tags(&self.tags);
+        HEADING_TAGS.iter().for_each(|heading_tag| {
+            builder.add_tag_attributes(heading_tag, &["id"]);
+        });
+        self.attributes.iter().for_each(|(tag, attributes)| {
+            builder.add_tag_attributes(tag.as_str(), attributes);
+        });
+        self.classes.iter().for_each(|(tag, classes)| {
+            builder.add_tag_attribute_values(tag.as_str(), "class", classes);
+        });
+        self.attribute_values
+            .iter()
+            .for_each(|(tag, attribute_values)| {
+                attribute_values.iter().for_each(|(attribute, values)| {
+                    builder.add_tag_attribute_values(tag.as_str(), attribute.as_str(), values);
+                });
+            });
+        if !self.url_schemes.is_empty() {
+            builder.url_schemes(self.url_schemes.iter().map(String::as_str).collect());
+        }
+        let document = builder.clean(unsafe_html_text);
+        self.remove_iframes(&document.to_dom_node());
+        document.to_string()
+    }
+
+    /// Ammonia can't look inside URLs, so iframes are filtered by host on the cleaned document instead
+    fn remove_iframes(&self, node: &Handle) {
+        node.children.borrow_mut().retain(|child| match child.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } if &*name.local == "iframe" => attrs
+                .borrow()
+                .iter()
+                .find(|attr| &*attr.name.local == "src")
+                .map_or(false, |src| self.is_iframe_src(&src.value)),
+            _ => true,
+        });
+        node.children
+            .borrow()
+            .iter()
+            .for_each(|child| self.remove_iframes(child));
+    }
+
+    fn is_iframe_src(&self, src: &str) -> bool {
+        Url::parse(src).ok().map_or(false, |url| {
+            (url.scheme() == "https" || url.scheme() == "http")
+                && url.host_str().map_or(false, |host| {
+                    self.iframe_hosts
+                        .iter()
+                        .any(|iframe_host| iframe_host.eq_ignore_ascii_case(host))
+                })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy() -> Policy {
+        serde_yaml::from_str(
+            "
+tags: [iframe, details, summary]
+attributes:
+  iframe: [src]
+  details: [open]
+iframe_hosts: [www.youtube-nocookie.com]
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn policy_allows_configured_tags() {
+        assert_eq!(
+            policy().clean("<details open><summary>More</summary>Hidden</details><dialog>"),
+            "<details open=\"\"><summary>More</summary>Hidden</details>"
+        );
+    }
+
+    #[test]
+    fn policy_files_add_to_the_default() {
+        let mut extended = Policy::default();
+        extended.extend(policy());
+        [Policy::default(), extended].iter().for_each(|policy| {
+            assert_eq!(
+                policy.clean(
+                    "<div id=\"fn1\" class=\"footnote-definition\"><sup class=\"footnote-definition-label\">1</sup></div>\
+                     <video controls=\"\" src=\"/files/demo.mp4\"></video>"
+                ),
+                "<div id=\"fn1\" class=\"footnote-definition\"><sup class=\"footnote-definition-label\">1</sup></div>\
+                 <video controls=\"\" src=\"/files/demo.mp4\"></video>"
+            );
+        });
+    }
+
+    #[test]
+    fn iframes_only_embed_allowed_hosts() {
+        let policy = policy();
+        assert_eq!(
+            policy.clean(
+                "<iframe src=\"https://www.youtube-nocookie.com/embed/x\"></iframe>\
+                 <iframe src=\"https://example.com\"></iframe><iframe src=\"/files\"></iframe><iframe></iframe>"
+            ),
+            "<iframe src=\"https://www.youtube-nocookie.com/embed/x\"></iframe>"
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! In-memory full-text search over blog posts. Posts are few and small, so an inverted index rebuilt whenever the blog changes is plenty.
+//! Words are lowercased and stemmed so that "plotting" finds "plotter", and documents are ranked with Okapi BM25.
+
+use base::push_escaped;
+use rust_stemmers::{Algorithm, Stemmer};
+use std::collections::{HashMap, HashSet};
+
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document length normalization
+const B: f64 = 0.75;
+
+/// How many words of context are shown on either side of the first match in a snippet
+const SNIPPET_CONTEXT: usize = 15;
+
+/// Words of `text` along with their byte offsets
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (start, c.is_alphanumeric()) {
+            (None, true) => start = Some(i),
+            (Some(word_start), false) => {
+                words.push((word_start, &text[word_start..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, &text[word_start..]));
+    }
+    words
+}
+
+fn stem(stemmer: &Stemmer, word: &str) -> String {
+    stemmer.stem(&word.to_lowercase()).into_owned()
+}
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    let stemmer = Stemmer::create(Algorithm::English);
+    words(text)
+        .into_iter()
+        .map(|(_, word)| stem(&stemmer, word))
+        .collect()
+}
+
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    /// Documents containing each term, with how many times it appears in them
+    postings: HashMap<String, Vec<(usize, usize)>>,
+    document_lengths: Vec<usize>,
+    average_length: f64,
+    /// Length of each document's TF-IDF vector
+    norms: Vec<f64>,
+}
+
+impl SearchIndex {
+    /// Documents are identified by their position in `documents`
+    pub fn new<'a, I: Iterator<Item = &'a str>>(documents: I) -> SearchIndex {
+        let mut postings: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+        let mut document_lengths = Vec::new();
+        documents.enumerate().for_each(|(document, text)| {
+            let terms = tokenize(text);
+            document_lengths.push(terms.len());
+            let mut frequencies: HashMap<String, usize> = HashMap::new();
+            terms
+                .into_iter()
+                .for_each(|term| *frequencies.entry(term).or_insert(0) += 1);
+            frequencies.into_iter().for_each(|(term, frequency)| {
+                postings
+                    .entry(term)
+                    .or_insert_with(Vec::new)
+                    .push((document, frequency))
+            });
+        });
+        let average_length = if document_lengths.is_empty() {
+            0.0
+        } else {
+            document_lengths.iter().sum::<usize>() as f64 / document_lengths.len() as f64
+        };
+        let mut norms = vec![0.0; document_lengths.len()];
+        postings.values().for_each(|postings| {
+            let idf = idf(document_lengths.len(), postings.len());
+            postings.iter().for_each(|&(document, frequency)| {
+                norms[document] += (frequency as f64 * idf).powi(2)
+            });
+        });
+        norms.iter_mut().for_each(|norm| *norm = norm.sqrt());
+        SearchIndex {
+            postings,
+            document_lengths,
+            average_length,
+            norms,
+        }
+    }
+
+    /// Other documents sharing terms with a document, most similar first. Similarity is the cosine of the angle between their TF-IDF
+    /// vectors.
+    pub fn similar(&self, document: usize) -> Vec<(usize, f64)> {
+        let mut dot_products: HashMap<usize, f64> = HashMap::new();
+        self.postings
+            .values()
+            .filter_map(|postings| {
+                postings
+                    .iter()
+                    .find(|&&(other, _)| other == document)
+                    .map(|&(_, frequency)| (postings, frequency))
+            })
+            .for_each(|(postings, frequency)| {
+                let idf = idf(self.document_lengths.len(), postings.len());
+                postings
+                    .iter()
+                    .filter(|&&(other, _)| other != document)
+                    .for_each(|&(other, other_frequency)| {
+                        *dot_products.entry(other).or_insert(0.0) +=
+                            frequency as f64 * other_frequency as f64 * idf * idf
+                    });
+            });
+        let mut ranked: Vec<(usize, f64)> = dot_products
+            .into_iter()
+            .filter(|&(_, dot_product)| dot_product > 0.0)
+            .map(|(other, dot_product)| {
+                (
+                    other,
+                    dot_product / (self.norms[document] * self.norms[other]),
+                )
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        ranked
+    }
+
+    /// Documents matching any term of the query, best first
+    pub fn search(&self, query: &str) -> Vec<(usize, f64)> {
+        let terms: HashSet<String> = tokenize(query).into_iter().collect();
+        let mut scores: HashMap<usize, f64> = HashMap::new();
+        terms
+            .iter()
+            .filter_map(|term| self.postings.get(term))
+            .for_each(|postings| {
+                let idf = idf(self.document_lengths.len(), postings.len());
+                postings.iter().for_each(|&(document, frequency)| {
+                    let frequency = frequency as f64;
+                    let length_ratio = self.document_lengths[document] as f64 / self.average_length;
+                    *scores.entry(document).or_insert(0.0) += idf * frequency * (K1 + 1.0)
+                        / (frequency + K1 * (1.0 - B + B * length_ratio));
+                });
+            });
+        let mut ranked: Vec<(usize, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        ranked
+    }
+}
+
+/// Inverse document frequency of a term, weighting rare terms over common ones. Never negative, unlike the textbook definition.
+fn idf(document_count: usize, containing: usize) -> f64 {
+    let (document_count, containing) = (document_count as f64, containing as f64);
+    ((document_count - containing + 0.5) / (containing + 0.5) + 1.0).ln()
+}
+
+/// An HTML excerpt of `text` around the first word matching the query, with every matching word in it wrapped in `<mark>`. Falls back
+/// to the start of the text if nothing matches.
+pub fn snippet(text: &str, query: &str) -> String {
+    let stemmer = Stemmer::create(Algorithm::English);
+    let terms: HashSet<String> = tokenize(query).into_iter().collect();
+    let words = words(text);
+    let first_match = words
+        .iter()
+        .position(|&(_, word)| terms.contains(&stem(&stemmer, word)))
+        .unwrap_or(0);
+    let first_word = first_match.saturating_sub(SNIPPET_CONTEXT);
+    let last_word = std::cmp::min(first_match + SNIPPET_CONTEXT, words.len());
+    if first_word >= last_word {
+        return String::new();
+    }
+
+    let mut html = String::new();
+    if first_word > 0 {
+        html.push_str("… ");
+    }
+    let mut offset = words[first_word].0;
+    words[first_word..last_word]
+        .iter()
+        .for_each(|&(word_offset, word)| {
+            push_escaped(&mut html, &text[offset..word_offset]);
+            if terms.contains(&stem(&stemmer, word)) {
+                html.push_str("<mark>");
+                push_escaped(&mut html, word);
+                html.push_str("</mark>");
+            } else {
+                push_escaped(&mut html, word);
+            }
+            offset = word_offset + word.len();
+        });
+    if last_word < words.len() {
+        html.push_str(" …");
+    }
+    html
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn words_are_lowercased_and_stemmed() {
+        assert_eq!(
+            tokenize("Plotting, plotted & PLOTS!"),
+            vec!["plot", "plot", "plot"]
+        );
+    }
+
+    #[test]
+    fn documents_are_ranked_by_relevance() {
+        let index = SearchIndex::new(
+            vec![
+                "Building a pen plotter",
+                "Lindenmayer systems drawn with a plotter. Plotters plot plots.",
+                "Outputting video over HDMI",
+            ]
+            .into_iter(),
+        );
+        let ranked: Vec<usize> = index
+            .search("plotter")
+            .into_iter()
+            .map(|(document, _)| document)
+            .collect();
+        assert_eq!(ranked, vec![1, 0]);
+        assert!(index.search("fpga").is_empty());
+    }
+
+    #[test]
+    fn similar_documents_share_terms() {
+        let index = SearchIndex::new(
+            vec![
+                "Building a pen plotter",
+                "Outputting video over HDMI",
+                "Plotting Lindenmayer systems with the pen plotter",
+            ]
+            .into_iter(),
+        );
+        let similar = index.similar(0);
+        assert_eq!(similar.len(), 1);
+        assert_eq!(similar[0].0, 2);
+        assert!(similar[0].1 > 0.0 && similar[0].1 <= 1.0);
+        assert!(index.similar(1).is_empty());
+    }
+
+    #[test]
+    fn snippets_highlight_matches() {
+        assert_eq!(
+            snippet("I built a <pen> plotter.", "plotters"),
+            "I built a &lt;pen&gt; <mark>plotter</mark>"
+        );
+    }
+}
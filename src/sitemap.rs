@@ -0,0 +1,81 @@
+//! A sitemap of every public page, so that crawlers find new posts without having to follow links to them and can tell which ones
+//! changed. robots.txt points crawlers here.
+
+use askama::Template;
+use base::*;
+use blog::BlogState;
+use chrono::{DateTime, Utc};
+use code_art::GalleryState;
+use meta::MetaImage;
+use rocket::State;
+
+pub struct SitemapUrl {
+    /// Relative to the site root
+    pub path: String,
+    pub lastmod: Option<DateTime<Utc>>,
+    /// Listed along with the page so that image search can find them
+    pub images: Vec<MetaImage>,
+}
+
+impl SitemapUrl {
+    pub fn new(path: &str, lastmod: Option<DateTime<Utc>>) -> SitemapUrl {
+        SitemapUrl {
+            path: path.to_string(),
+            lastmod,
+            images: Vec::new(),
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "sitemap.xml")]
+pub struct Sitemap {
+    public_url: &'static str,
+    urls: Vec<SitemapUrl>,
+}
+
+#[get("/sitemap.xml")]
+pub fn get_sitemap(blog_state: State<BlogState>, gallery_state: State<GalleryState>) -> Sitemap {
+    let mut urls = vec![SitemapUrl::new("/", None), SitemapUrl::new("/about", None)];
+    urls.extend(blog_state.read().unwrap().sitemap_urls());
+    urls.extend(gallery_state.read().unwrap().sitemap_urls());
+    Sitemap {
+        public_url: &PUBLIC_URL,
+        urls,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use blog::test::load_posts;
+
+    #[test]
+    fn sitemap_lists_published_posts_and_encoded_tags() {
+        let blog = load_posts(
+            "sitemap",
+            &[
+                ("plotter.md", "---\ntitle: Pen Plotter\ntags: [c#]\n---\n"),
+                (
+                    "unfinished.md",
+                    "---\ntitle: Unfinished\ndraft: true\n---\n",
+                ),
+                (
+                    "scheduled.md",
+                    "---\ntitle: Scheduled\npublish_at: 2999-01-01\n---\n",
+                ),
+            ],
+        );
+        let xml = Sitemap {
+            public_url: &PUBLIC_URL,
+            urls: blog.sitemap_urls(),
+        }
+        .render()
+        .unwrap();
+        assert!(xml.contains("pen-plotter</loc>"));
+        assert!(xml.contains("tag&#x2f;c%23</loc>"));
+        assert!(!xml.contains("c#"));
+        assert!(!xml.contains("unfinished"));
+        assert!(!xml.contains("scheduled"));
+    }
+}
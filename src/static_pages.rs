@@ -1,5 +1,6 @@
 use askama::Template;
 use base::*;
+use meta::{self, PageMeta};
 use rocket::State;
 
 #[derive(Template)]
@@ -8,6 +9,17 @@ pub struct About {
     _parent: Base,
 }
 
+impl About {
+    fn meta(&self) -> PageMeta {
+        PageMeta::website(
+            "AboutPage",
+            "About Me",
+            "A bit about me and the things I make.",
+            "/about",
+        )
+    }
+}
+
 #[get("/about")]
 pub fn get_about(state: State<Base>) -> About {
     About {
@@ -21,6 +33,12 @@ pub struct Index {
     _parent: Base,
 }
 
+impl Index {
+    fn meta(&self) -> PageMeta {
+        PageMeta::website("WebSite", "purisa.me", meta::SITE_DESCRIPTION, "/")
+    }
+}
+
 #[get("/")]
 pub fn get_index(req: State<Base>) -> Index {
     Index {